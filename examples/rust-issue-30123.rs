@@ -1,9 +1,8 @@
-extern crate multirust;
 extern crate rust_bisect;
 extern crate rust_install;
 
 use std::env;
-use std::ffi::OsStr;
+use std::ffi::OsString;
 use std::path::PathBuf;
 
 ///! This example is equivalent to running
@@ -13,17 +12,15 @@ fn main() {
     let manifest_dir = PathBuf::from(&env::var("CARGO_MANIFEST_DIR").unwrap());
     env::set_current_dir(manifest_dir.join("examples/rust-issue-30123")).unwrap();
 
-    let cmd = OsStr::new("cargo");
-    let args = vec![OsStr::new("build")];
-
     let cfg = rust_bisect::Cfg {
-        good: "nightly-2015-10-27".parse().unwrap(),
-        bad: "nightly-2015-11-30".parse().unwrap(),
-        cmd: cmd,
-        args: args,
+        good: Some("nightly-2015-10-27".parse().unwrap()),
+        bad: Some("nightly-2015-11-30".parse().unwrap()),
+        cmd: Some(OsString::from("cargo")),
+        args: vec![OsString::from("build")],
+        ..rust_bisect::Cfg::default()
     };
 
-    let mr_cfg = multirust::Cfg::from_env(rust_bisect::cli::notify_handler())
+    let mr_cfg = rust_bisect::load_mr_cfg(rust_bisect::cli::notify_handler(cfg.format))
                      .expect("multirust config");
 
     rust_bisect::run(&cfg, &mr_cfg).unwrap();
@@ -1,37 +1,454 @@
-/// Finds the index of the least item in `slice` for which the `predicate` holds.
-// Adapted from core::slice::binary_search_by().
-pub fn least_satisfying<T, P>(slice: &[T], mut predicate: P) -> usize
-    where P: FnMut(&T) -> bool
+use std::ops::Range;
+
+/// The outcome of testing an item against a predicate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Satisfies {
+    Yes,
+    No,
+    /// The item could not be tested.
+    Unknown,
+}
+
+impl From<bool> for Satisfies {
+    fn from(b: bool) -> Satisfies {
+        if b {
+            Satisfies::Yes
+        } else {
+            Satisfies::No
+        }
+    }
+}
+
+/// Finds the least item in `slice` for which the `predicate` holds.
+///
+/// Items for which the predicate is `Unknown` are stepped around by testing their neighbours
+/// instead. The returned range `lo..hi` is such that the least satisfying item is either at `hi`,
+/// or is one of the untestable items in `lo..hi`; the range is empty unless untestable items sit
+/// on the boundary. If the predicate is not known to hold anywhere, `hi` is `slice.len()`.
+///
+/// The predicate is never tested outside `slice`: it is assumed not to hold before it, and to hold
+/// after it. Callers that can't be sure of that should check the ends first.
+pub fn least_satisfying<T, P>(slice: &[T], mut predicate: P) -> Range<usize>
+    where P: FnMut(&T) -> Satisfies
 {
-    // TODO: assert if fails and succeeds at the ends?
-    // TODO: make it return Option<usize>, in case predicate holds nowhere.
-    let mut base = 0usize;
-    let mut s = slice;
+    let mut search = Search::new(slice.len());
+    while let Some(idx) = search.next() {
+        let satisfies = predicate(&slice[idx]);
+        search.record(idx, satisfies);
+    }
+    search.bounds()
+}
 
+/// Like `least_satisfying`, but tests up to `k` items at a time, for when the predicate can be
+/// evaluated for several items at once.
+///
+/// Each round splits the remaining range into `k + 1` segments, and `predicate` is given the
+/// items at the split points, in order, to return an outcome for each. The search narrows to the
+/// segment where the outcomes first flip.
+pub fn least_satisfying_k<T, P>(slice: &[T], k: usize, mut predicate: P) -> Range<usize>
+    where P: FnMut(&[&T]) -> Vec<Satisfies>
+{
+    let mut search = Search::new(slice.len());
     loop {
-        let (head, tail) = s.split_at(s.len() >> 1);
-        if tail.is_empty() {
-            return base + head.len();
+        let batch = search.next_batch(k);
+        if batch.is_empty() {
+            break;
+        }
+        let items: Vec<_> = batch.iter().map(|&idx| &slice[idx]).collect();
+        for (&idx, satisfies) in batch.iter().zip(predicate(&items)) {
+            search.record(idx, satisfies);
         }
-        if predicate(&tail[0]) {
-            s = head;
+    }
+    search.bounds()
+}
+
+/// A search for the least satisfying item that is driven one step at a time, for when outcomes
+/// arrive from outside a single call to `least_satisfying`.
+#[derive(Clone, Debug)]
+pub struct Search {
+    lo: usize,
+    hi: usize,
+    unknown: Vec<bool>,
+}
+
+impl Search {
+    /// Starts a search across `len` items.
+    pub fn new(len: usize) -> Search {
+        Search {
+            lo: 0,
+            hi: len,
+            unknown: vec![false; len],
+        }
+    }
+
+    /// Returns the index of the item to test next, or `None` if the search is over.
+    pub fn next(&self) -> Option<usize> {
+        testable_midpoint(self.lo, self.hi, &self.unknown)
+    }
+
+    /// Returns the indices of up to `k` items to test at once, in increasing order, splitting the
+    /// remaining range into `k + 1` segments of about the same size.
+    pub fn next_batch(&self, k: usize) -> Vec<usize> {
+        let len = self.hi - self.lo;
+        // Each split point is looked for around its ideal position, in a window of its own so
+        // that the batch doesn't crowd around the same untestable items.
+        let window_edge = |i: usize| self.lo + (len * i + k + 1) / (2 * (k + 1));
+        let batch: Vec<_> = (1..(k + 1))
+                                .filter_map(|i| {
+                                    testable_midpoint(window_edge(2 * i - 1),
+                                                      window_edge(2 * i + 1),
+                                                      &self.unknown)
+                                })
+                                .collect();
+        // The windows can all be empty or untestable when few items remain.
+        if batch.is_empty() {
+            self.next().into_iter().collect()
         } else {
-            base += head.len() + 1;
-            s = &tail[1..];
+            batch
         }
     }
+
+    /// Records the outcome of testing the item at `idx`.
+    ///
+    /// Outcomes that contradict what is already known, which can happen when a batch is recorded
+    /// and the predicate isn't monotonic, are ignored.
+    pub fn record(&mut self, idx: usize, satisfies: Satisfies) {
+        if idx < self.lo || idx >= self.hi {
+            return;
+        }
+        match satisfies {
+            Satisfies::Yes => self.hi = idx,
+            Satisfies::No => self.lo = idx + 1,
+            Satisfies::Unknown => self.unknown[idx] = true,
+        }
+    }
+
+    /// Returns the range `lo..hi` such that the least satisfying item is either at `hi` or in
+    /// `lo..hi`. Once the search is over, the items in `lo..hi` are all untestable.
+    pub fn bounds(&self) -> Range<usize> {
+        self.lo..self.hi
+    }
+}
+
+/// A search for the least satisfying item that allows for the predicate giving the wrong outcome
+/// now and then, as a flaky command does.
+///
+/// Rather than ruling out part of the range with each outcome, it keeps a probability for each
+/// item being the least satisfying one, starting out uniform, and updates them assuming each
+/// outcome is wrong with probability `error_rate`. No outcome is final, so an item may be tested
+/// more than once. The item tested next is the one most likely to split the remaining probability
+/// in half, which is the test whose outcome is the most informative. The search is over once one
+/// item's probability reaches `confidence`, or after a fixed number of tests in case the outcomes
+/// never settle.
+///
+/// As with `Search`, index `len` stands for the item just past the end, which is assumed to
+/// satisfy the predicate.
+#[derive(Clone, Debug)]
+pub struct Posterior {
+    /// The probability of each index in `0..len + 1` being the least satisfying one.
+    probs: Vec<f64>,
+    unknown: Vec<bool>,
+    error_rate: f64,
+    confidence: f64,
+    tests: usize,
+    max_tests: usize,
+}
+
+impl Posterior {
+    /// Starts a search across `len` items.
+    pub fn new(len: usize, error_rate: f64, confidence: f64) -> Posterior {
+        assert!(0.0 < error_rate && error_rate < 0.5,
+                "`error_rate` must be between 0 and 0.5");
+        // Enough for every step of a binary search to be repeated a few times over.
+        let steps = (len + 1).next_power_of_two().trailing_zeros() as usize;
+        Posterior {
+            probs: vec![1.0 / (len + 1) as f64; len + 1],
+            unknown: vec![false; len],
+            error_rate: error_rate,
+            confidence: confidence,
+            tests: 0,
+            max_tests: 4 * (steps + 1),
+        }
+    }
+
+    /// Returns the index of the item to test next, or `None` if the search is over.
+    pub fn next(&self) -> Option<usize> {
+        self.next_batch(1).pop()
+    }
+
+    /// Returns the indices of up to `k` items to test at once, in increasing order, splitting the
+    /// probability into `k + 1` parts of about the same size.
+    pub fn next_batch(&self, k: usize) -> Vec<usize> {
+        if self.tests >= self.max_tests || self.confidence() >= self.confidence {
+            return Vec::new();
+        }
+        let cumulative = self.cumulative();
+        let mut batch: Vec<usize> = (1..(k + 1))
+                                        .filter_map(|i| {
+                                            let target = i as f64 / (k + 1) as f64;
+                                            self.nearest_testable(&cumulative, target)
+                                        })
+                                        .collect();
+        batch.sort();
+        batch.dedup();
+        batch
+    }
+
+    /// Records the outcome of testing the item at `idx`.
+    pub fn record(&mut self, idx: usize, satisfies: Satisfies) {
+        if idx >= self.unknown.len() {
+            return;
+        }
+        let satisfies = match satisfies {
+            Satisfies::Yes => true,
+            Satisfies::No => false,
+            Satisfies::Unknown => {
+                self.unknown[idx] = true;
+                return;
+            }
+        };
+        // The item at `idx` satisfies the predicate if the least satisfying item is at or before
+        // it, so the outcome is right for those and wrong for the rest, or the other way around.
+        for (least, prob) in self.probs.iter_mut().enumerate() {
+            *prob *= if (least <= idx) == satisfies {
+                1.0 - self.error_rate
+            } else {
+                self.error_rate
+            };
+        }
+        let total = self.probs.iter().fold(0.0, |total, prob| total + prob);
+        for prob in &mut self.probs {
+            *prob /= total;
+        }
+        self.tests += 1;
+    }
+
+    /// Returns the most likely range `lo..hi` such that the least satisfying item is either at
+    /// `hi` or in `lo..hi`, where the items in `lo..hi` are all untestable.
+    pub fn bounds(&self) -> Range<usize> {
+        self.most_likely().0
+    }
+
+    /// The probability that the least satisfying item is in the range given by `bounds`.
+    pub fn confidence(&self) -> f64 {
+        self.most_likely().1
+    }
+
+    /// Finds the most likely range of untestable items followed by a testable one, or by the end.
+    /// Nothing can tell the items in such a range apart, so they are counted together.
+    fn most_likely(&self) -> (Range<usize>, f64) {
+        let mut best = (0..0, 0.0);
+        let mut lo = 0;
+        let mut prob = 0.0;
+        for hi in 0..self.probs.len() {
+            prob += self.probs[hi];
+            if hi < self.unknown.len() && self.unknown[hi] {
+                continue;
+            }
+            if prob > best.1 {
+                best = (lo..hi, prob);
+            }
+            lo = hi + 1;
+            prob = 0.0;
+        }
+        best
+    }
+
+    /// The probability of the least satisfying item being at or before each index.
+    fn cumulative(&self) -> Vec<f64> {
+        self.probs
+            .iter()
+            .scan(0.0, |total, &prob| {
+                *total += prob;
+                Some(*total)
+            })
+            .collect()
+    }
+
+    /// Finds the testable item whose `cumulative` probability is nearest `target`.
+    fn nearest_testable(&self, cumulative: &[f64], target: f64) -> Option<usize> {
+        let distance = |idx: usize| (cumulative[idx] - target).abs();
+        (0..self.unknown.len())
+            .filter(|&idx| !self.unknown[idx])
+            .fold(None, |best, idx| {
+                match best {
+                    Some(best) if distance(best) <= distance(idx) => Some(best),
+                    _ => Some(idx),
+                }
+            })
+    }
+}
+
+/// Finds the index in `lo..hi` nearest the midpoint that is not known to be untestable.
+fn testable_midpoint(lo: usize, hi: usize, unknown: &[bool]) -> Option<usize> {
+    let mid = lo + (hi - lo) / 2;
+    for distance in 0..(hi - lo) {
+        if mid + distance < hi && !unknown[mid + distance] {
+            return Some(mid + distance);
+        }
+        if mid >= lo + distance && !unknown[mid - distance] {
+            return Some(mid - distance);
+        }
+    }
+    None
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::Satisfies::*;
 
     #[test]
     fn test_bisect() {
         let s = &[0, 3, 7, 10, 33, 169, 222, 223];
-        assert_eq!(0, least_satisfying(s, |&x| x >= 0));
-        assert_eq!(5, least_satisfying(s, |&x| x >= 50));
-        assert_eq!(1, least_satisfying(s, |&x| x >= 3));
-        assert_eq!(2, least_satisfying(s, |&x| x > 3));
+        assert_eq!(0..0, least_satisfying(s, |&x| (x >= 0).into()));
+        assert_eq!(5..5, least_satisfying(s, |&x| (x >= 50).into()));
+        assert_eq!(1..1, least_satisfying(s, |&x| (x >= 3).into()));
+        assert_eq!(2..2, least_satisfying(s, |&x| (x > 3).into()));
+        assert_eq!(8..8, least_satisfying(s, |&x| (x > 500).into()));
+    }
+
+    #[test]
+    fn test_bisect_unknown() {
+        let s = &[0, 3, 7, 10, 33, 169, 222, 223];
+        let pred = |limit| {
+            move |&x: &i32| {
+                if x == 33 {
+                    Unknown
+                } else {
+                    (x >= limit).into()
+                }
+            }
+        };
+        assert_eq!(6..6, least_satisfying(s, pred(222)));
+        assert_eq!(4..5, least_satisfying(s, pred(169)));
+        assert_eq!(4..5, least_satisfying(s, pred(33)));
+        assert_eq!(3..3, least_satisfying(s, pred(10)));
+        assert_eq!(0..8, least_satisfying(s, |_| Unknown));
+    }
+
+    #[test]
+    fn test_bisect_k() {
+        let s = &[0, 3, 7, 10, 33, 169, 222, 223];
+        let pred = |limit| {
+            move |xs: &[&i32]| {
+                xs.iter()
+                  .map(|&&x| {
+                      if x == 33 {
+                          Unknown
+                      } else {
+                          (x >= limit).into()
+                      }
+                  })
+                  .collect()
+            }
+        };
+        for k in 1..10 {
+            assert_eq!(0..0, least_satisfying_k(s, k, pred(0)));
+            assert_eq!(2..2, least_satisfying_k(s, k, pred(4)));
+            assert_eq!(4..5, least_satisfying_k(s, k, pred(169)));
+            assert_eq!(6..6, least_satisfying_k(s, k, pred(222)));
+            assert_eq!(8..8, least_satisfying_k(s, k, pred(500)));
+        }
+        assert_eq!(0..8, least_satisfying_k(s, 3, |xs| vec![Unknown; xs.len()]));
+    }
+
+    #[test]
+    fn test_search_batch() {
+        let mut search = Search::new(12);
+        assert_eq!(vec![3, 6, 9], search.next_batch(3));
+        // The first flip wins if outcomes contradict each other.
+        search.record(3, No);
+        search.record(6, Yes);
+        search.record(9, No);
+        assert_eq!(4..6, search.bounds());
+        assert_eq!(vec![4, 5], search.next_batch(3));
+        search.record(5, Unknown);
+        assert_eq!(vec![4], search.next_batch(3));
+    }
+
+    /// Drives a `Posterior` search across `s`, with the predicate giving the wrong outcome for
+    /// the tests numbered in `wrong`.
+    fn posterior_search<P>(s: &[i32], wrong: &[usize], pred: P) -> (Range<usize>, f64)
+        where P: Fn(i32) -> Satisfies
+    {
+        let mut search = Posterior::new(s.len(), 0.1, 0.95);
+        let mut tests = 0;
+        while let Some(idx) = search.next() {
+            let satisfies = match pred(s[idx]) {
+                Yes if wrong.contains(&tests) => No,
+                No if wrong.contains(&tests) => Yes,
+                satisfies => satisfies,
+            };
+            search.record(idx, satisfies);
+            tests += 1;
+        }
+        (search.bounds(), search.confidence())
+    }
+
+    #[test]
+    fn test_posterior() {
+        let s = &[0, 3, 7, 10, 33, 169, 222, 223];
+        for &(limit, expected) in &[(0, 0), (4, 2), (33, 4), (169, 5), (500, 8)] {
+            let (bounds, confidence) = posterior_search(s, &[], |x| (x >= limit).into());
+            assert_eq!(expected..expected, bounds);
+            assert!(confidence >= 0.95);
+        }
+    }
+
+    #[test]
+    fn test_posterior_wrong_outcome() {
+        let s = &[0, 3, 7, 10, 33, 169, 222, 223];
+        // A binary search would be sent the wrong way for good by any of these.
+        for wrong in 0..3 {
+            let (bounds, confidence) = posterior_search(s, &[wrong], |x| (x >= 10).into());
+            assert_eq!(3..3, bounds);
+            assert!(confidence >= 0.95);
+        }
+    }
+
+    #[test]
+    fn test_posterior_unknown() {
+        let s = &[0, 3, 7, 10, 33, 169, 222, 223];
+        let pred = |limit| {
+            move |x| {
+                if x == 33 {
+                    Unknown
+                } else {
+                    (x >= limit).into()
+                }
+            }
+        };
+        assert_eq!(4..5, posterior_search(s, &[], pred(169)).0);
+        assert_eq!(4..5, posterior_search(s, &[], pred(33)).0);
+        assert_eq!(6..6, posterior_search(s, &[], pred(222)).0);
+
+        let mut search = Posterior::new(4, 0.1, 0.95);
+        for idx in 0..4 {
+            search.record(idx, Unknown);
+        }
+        assert_eq!(None, search.next());
+    }
+
+    #[test]
+    fn test_posterior_batch() {
+        let mut search = Posterior::new(11, 0.1, 0.95);
+        assert_eq!(vec![2, 5, 8], search.next_batch(3));
+        search.record(5, Yes);
+        let batch = search.next_batch(3);
+        assert!(batch.iter().all(|&idx| idx <= 5), "{:?}", batch);
+    }
+
+    #[test]
+    fn test_search() {
+        let mut search = Search::new(8);
+        assert_eq!(Some(4), search.next());
+        search.record(2, Yes);
+        assert_eq!(0..2, search.bounds());
+        search.record(1, Unknown);
+        assert_eq!(Some(0), search.next());
+        search.record(0, No);
+        assert_eq!(None, search.next());
+        assert_eq!(1..2, search.bounds());
     }
 }
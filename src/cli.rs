@@ -1,14 +1,21 @@
 use std;
+use std::ffi::OsString;
 use std::fmt;
+use std::path::{Path, PathBuf};
 
-use clap::{App, AppSettings, Arg, ArgMatches};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use multirust;
+use regex::Regex;
 use rust_install::dist::ToolchainDesc;
+use semver;
 use term;
 
-use {NIGHTLY, Cfg, Error, Nightly, Result};
+use config::{CONFIG_FILE, FileCfg};
+use install::MINIMAL_COMPONENTS;
+use {NIGHTLY, Cfg, Error, Format, Regress, Release, Result, Timeout, Verdict, Version, Vote,
+     check_order, resolve_releases};
 
-pub fn notify_handler() -> multirust::SharedNotifyHandler {
+pub fn notify_handler(format: Format) -> multirust::SharedNotifyHandler {
     shared_ntfy!(move |n: multirust::Notification| {
         use multirust::notify::NotificationLevel::*;
         match n.level() {
@@ -17,79 +24,564 @@ pub fn notify_handler() -> multirust::SharedNotifyHandler {
                 display_error(n).unwrap();
             }
             _ => {
-                println!("{}", n);
+                progress!(format, "{}", n);
             }
         }
     })
 }
 
-pub fn app() -> App<'static, 'static> {
-    fn validate_version(s: String) -> std::result::Result<(), String> {
-        let ret = ToolchainDesc::from_str(&s);
-        match ret {
-            Some(ref desc) if desc.channel == NIGHTLY && desc.date.is_some() => Ok(()),
-            Some(_) => Err(String::from("can only bisect on dated nightlies")),
-            None => Err(String::from(format!("invalid version: {}", s))),
+fn validate_version(s: String) -> std::result::Result<(), String> {
+    if s.parse::<Version>().is_ok() {
+        return Ok(());
+    }
+    // Release numbers that can't be bisected across, like betas, get the reason why.
+    if semver::Version::parse(&s).is_ok() {
+        if let Err(e) = s.parse::<Release>() {
+            return Err(e.to_string());
         }
     }
+    match ToolchainDesc::from_str(&s) {
+        Some(_) => Err(String::from("can only bisect on dated nightlies and stable releases")),
+        None => Err(String::from(format!("invalid version: {}", s))),
+    }
+}
+
+fn validate_host(s: String) -> std::result::Result<(), String> {
+    // Hosts are put in toolchain names, so they have to be ones multirust-rs can parse.
+    match ToolchainDesc::from_str(&format!("{}-{}", s, NIGHTLY)) {
+        Some(ref desc) if desc.arch.is_some() && desc.os.is_some() => Ok(()),
+        _ => Err(format!("unsupported host: {}", s)),
+    }
+}
+
+fn validate_secs(s: String) -> std::result::Result<(), String> {
+    match s.parse::<u64>() {
+        Ok(0) => Err(String::from("must be at least one second")),
+        Ok(_) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn validate_count(s: String) -> std::result::Result<(), String> {
+    match s.parse::<usize>() {
+        Ok(0) => Err(String::from("must be at least 1")),
+        Ok(_) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn validate_regex(s: String) -> std::result::Result<(), String> {
+    Regex::new(&s).map(|_| ()).map_err(|e| e.to_string())
+}
+
+/// The `--good` and `--bad` endpoints and `--find-fix`, shared by the top level and `start`. The
+/// endpoints can also come from the config file, so `good` being required is only checked once
+/// that has been read.
+///
+/// Besides `nightly-YYYY-MM-DD` and stable releases, a nightly can be given as a bare date or
+/// relative to today, as in `today`, `last-week` or `-30d`. A value starting with `-` has to be
+/// attached with `=`, as in `--good=-30d`.
+fn endpoint_args(good_required: bool) -> Vec<Arg<'static, 'static>> {
+    vec![Arg::with_name("good")
+             .long("good")
+             .takes_value(true)
+             .value_name("VERSION")
+             .help(if good_required {
+                 "A known good nightly or stable release"
+             } else {
+                 "A known good nightly or stable release; if left out, older nightlies are \
+                  tested until a good one is found"
+             })
+             .validator(validate_version),
+         Arg::with_name("bad")
+             .long("bad")
+             .takes_value(true)
+             .value_name("VERSION")
+             .help("A known bad nightly or stable release [default: the latest nightly]")
+             .validator(validate_version),
+         Arg::with_name("find-fix")
+             .long("find-fix")
+             .help("Look for the first version where the command starts succeeding; `--bad` is \
+                    then the older endpoint, and is required, and `--good` defaults to the \
+                    latest nightly")]
+}
+
+/// Arguments for running the command, shared by the top level, `start` and `run`. The command can
+/// also come from the config file, so it is only checked for once that has been read.
+fn run_args() -> Vec<Arg<'static, 'static>> {
+    vec![Arg::with_name("config")
+             .long("config")
+             .takes_value(true)
+             .value_name("PATH")
+             .help("Read settings from the config file at PATH [default: bisect.toml, if it \
+                    exists]"),
+         Arg::with_name("refine")
+             .long("refine")
+             .help("After bisecting stable releases, bisect the nightlies between the last good \
+                    and first bad release"),
+         Arg::with_name("interactive")
+             .long("interactive")
+             .help("Ask for a verdict on each version instead of deciding from COMMAND's exit \
+                    code; COMMAND is optional, and is run before asking"),
+         Arg::with_name("regress")
+             .long("regress")
+             .takes_value(true)
+             .value_name("KIND")
+             .possible_values(&["error", "success", "ice", "non-ice"])
+             .default_value("error")
+             .help("What makes a version bad: COMMAND failing (error), succeeding (success), \
+                    hitting an internal compiler error (ice), or failing in some other way \
+                    (non-ice)"),
+         Arg::with_name("timeout")
+             .long("timeout")
+             .takes_value(true)
+             .value_name("SECONDS")
+             .help("Kill COMMAND, and everything it started, if it runs for longer than SECONDS")
+             .validator(validate_secs),
+         Arg::with_name("timeout-verdict")
+             .long("timeout-verdict")
+             .takes_value(true)
+             .value_name("VERDICT")
+             .possible_values(&["good", "bad", "skip"])
+             .help("How to treat a version with which COMMAND times out [default: bad]"),
+         Arg::with_name("bad-if-output-matches")
+             .long("bad-if-output-matches")
+             .takes_value(true)
+             .value_name("REGEX")
+             .help("Treat a version as bad if COMMAND's stdout or stderr matches REGEX, whatever \
+                    its exit code")
+             .validator(validate_regex),
+         Arg::with_name("good-if-output-matches")
+             .long("good-if-output-matches")
+             .takes_value(true)
+             .value_name("REGEX")
+             .help("Treat a version as good if COMMAND's stdout or stderr matches REGEX, whatever \
+                    its exit code")
+             .validator(validate_regex),
+         Arg::with_name("jobs")
+             .long("jobs")
+             .short("j")
+             .takes_value(true)
+             .value_name("N")
+             .validator(validate_count)
+             .conflicts_with("interactive")
+             .help("Test N versions at once, splitting the remaining range into N + 1 parts; \
+                    implies --isolate-target-dir"),
+         Arg::with_name("runs")
+             .long("runs")
+             .takes_value(true)
+             .value_name("N")
+             .validator(validate_count)
+             .conflicts_with("interactive")
+             .help("Run COMMAND N times on each version, for flaky commands, and combine the \
+                    verdicts with `--vote`"),
+         Arg::with_name("vote")
+             .long("vote")
+             .takes_value(true)
+             .value_name("VOTE")
+             .possible_values(&["any-fail", "majority", "all-fail"])
+             .default_value("any-fail")
+             .help("With `--runs`, whether a version is bad if any run was bad, if most were, or \
+                    only if all were"),
+         Arg::with_name("probabilistic")
+             .long("probabilistic")
+             .help("Weigh each verdict against the others rather than taking it as final, so \
+                    that a wrong one from a flaky command can be outvoted, and report how likely \
+                    the result is"),
+         Arg::with_name("minimal")
+             .long("minimal")
+             .help("Install only rustc, rust-std and cargo for each nightly, instead of the whole \
+                    rust package"),
+         Arg::with_name("component")
+             .long("component")
+             .takes_value(true)
+             .value_name("NAME")
+             .multiple(true)
+             .number_of_values(1)
+             .help("Install component NAME, such as rust-src, for each nightly too; implies \
+                    `--minimal`"),
+         Arg::with_name("target")
+             .long("target")
+             .takes_value(true)
+             .value_name("TRIPLE")
+             .multiple(true)
+             .number_of_values(1)
+             .help("Install the standard library for TRIPLE too, skipping nightlies that don't \
+                    have it"),
+         Arg::with_name("host")
+             .long("host")
+             .takes_value(true)
+             .value_name("TRIPLE")
+             .validator(validate_host)
+             .help("Bisect toolchains built for TRIPLE instead of the native host, skipping \
+                    nightlies that don't have a build for it"),
+         Arg::with_name("format")
+             .long("format")
+             .takes_value(true)
+             .value_name("FORMAT")
+             .possible_values(&["text", "json"])
+             .default_value("text")
+             .help("How to report the result: as prose (text), or as JSON with every step and \
+                    the commit hashes of the last good and first bad versions (json)"),
+         Arg::with_name("output")
+             .long("output")
+             .takes_value(true)
+             .value_name("FILE")
+             .help("Save the result to FILE as JSON, whatever `--format` is"),
+         Arg::with_name("log-dir")
+             .long("log-dir")
+             .takes_value(true)
+             .value_name("DIR")
+             .help("Save COMMAND's output with each version in DIR, along with what it was run \
+                    with and how it went"),
+         Arg::with_name("quiet-steps")
+             .long("quiet-steps")
+             .requires("log-dir")
+             .help("Only show COMMAND's output when it fails; otherwise it only goes to the logs"),
+         Arg::with_name("isolate-target-dir")
+             .long("isolate-target-dir")
+             .help("Build with each version in a CARGO_TARGET_DIR of its own, under .rust-bisect"),
+         Arg::with_name("keep-toolchains")
+             .long("keep-toolchains")
+             .help("Don't remove the toolchains installed for bisecting, or their target dirs, \
+                    once it is done"),
+         Arg::with_name("no-prefetch")
+             .long("no-prefetch")
+             .help("Don't download the versions that might be tested next while COMMAND runs"),
+         Arg::with_name("no-verify")
+             .long("no-verify")
+             .help("Don't check that the command succeeds with the good version and fails with \
+                    the bad one before bisecting"),
+         Arg::with_name("rust-file")
+             .long("rust-file")
+             .takes_value(true)
+             .value_name("FILE")
+             .conflicts_with("COMMAND")
+             .help("Compile the single-file program FILE with each version's rustc instead of \
+                    running COMMAND"),
+         Arg::with_name("run")
+             .long("run")
+             .requires("rust-file")
+             .help("Run the program compiled from `--rust-file` too, rather than just compiling \
+                    it"),
+         Arg::with_name("COMMAND")
+             .index(1)
+             .help("The command to run"),
+         Arg::with_name("ARGS")
+             .index(2)
+             .multiple(true)
+             .help("Arguments for COMMAND")]
+}
+
+fn verdict_subcommand(name: &'static str, about: &'static str) -> App<'static, 'static> {
+    SubCommand::with_name(name)
+        .about(about)
+        .arg(Arg::with_name("VERSION")
+                 .index(1)
+                 .help("The version to mark, instead of the one to be tested next")
+                 .validator(validate_version))
+}
+
+pub fn app() -> App<'static, 'static> {
     App::new("rust-bisect")
         .author("Kamal Marhubi <kamal@marhubi.com>")
         .about("Find the Rust nightly that that changed some behavior")
         .setting(AppSettings::TrailingVarArg)
-        .usage("rust-bisect [FLAGS] --bad <VERSION> --good <VERSION> <COMMAND> [ARGS...]")
-        .arg(Arg::with_name("good")
-                 .long("good")
-                 .takes_value(true)
-                 .value_name("VERSION")
-                 .help("A known good nightly release")
-                 .validator(validate_version)
-                 .required(true))
-        .arg(Arg::with_name("bad")
-                 .long("bad")
-                 .takes_value(true)
-                 .value_name("VERSION")
-                 .help("A known bad nightly release")
-                 .validator(validate_version)
-                 .required(true))
-        .arg(Arg::with_name("COMMAND")
-                 .index(1)
-                 .help("The command to run")
-                 .required(true))
-        .arg(Arg::with_name("ARGS")
-                 .index(2)
-                 .multiple(true)
-                 .help("Arguments for COMMAND"))
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .usage("rust-bisect [FLAGS] [--bad <VERSION>] [--good <VERSION>] <COMMAND> [ARGS...]\n    \
+                rust-bisect <SUBCOMMAND>")
+        .args(&endpoint_args(false))
+        .args(&run_args())
+        .subcommand(SubCommand::with_name("start")
+                        .about("Start a session to be continued with the other subcommands")
+                        .setting(AppSettings::TrailingVarArg)
+                        .args(&endpoint_args(true))
+                        .args(&run_args()))
+        .subcommand(verdict_subcommand("good", "Mark a version as good"))
+        .subcommand(verdict_subcommand("bad", "Mark a version as bad"))
+        .subcommand(verdict_subcommand("skip", "Mark a version as untestable"))
+        .subcommand(SubCommand::with_name("status").about("Show the progress of the session"))
+        .subcommand(SubCommand::with_name("reset")
+                        .about("End the session, removing its toolchains")
+                        .arg(Arg::with_name("keep-toolchains")
+                                 .long("keep-toolchains")
+                                 .help("Don't remove the session's toolchains")))
+        .subcommand(SubCommand::with_name("gc")
+                        .about("Remove all toolchains installed for bisecting, including ones \
+                                left behind by interrupted runs"))
+        .subcommand(SubCommand::with_name("run")
+                        .about("Continue the session, running COMMAND for each remaining step")
+                        .setting(AppSettings::TrailingVarArg)
+                        .args(&run_args()))
 }
 
-impl<'a> Cfg<'a> {
-    pub fn from_matches(matches: &'a ArgMatches<'a>) -> Result<Cfg<'a>> {
-        let good = try!(matches.value_of("good").ok_or("missing arg: `good`"));
-        let good: Nightly = try!(good.parse());
+impl Cfg {
+    /// Builds the configuration from the command line, filling in anything it doesn't give from
+    /// the config file.
+    pub fn from_matches(matches: &ArgMatches) -> Result<Cfg> {
+        Cfg::build(matches, false)
+    }
+
+    /// Like `from_matches`, but for continuing a session with `rust-bisect run`. The session's
+    /// endpoints and `regress` are used, so the config file's aren't read.
+    pub fn from_run_matches(matches: &ArgMatches) -> Result<Cfg> {
+        Cfg::build(matches, true)
+    }
+
+    fn build(matches: &ArgMatches, resuming: bool) -> Result<Cfg> {
+        let config = match matches.value_of_os("config") {
+            Some(path) => Some(PathBuf::from(path)),
+            None if Path::new(CONFIG_FILE).exists() => Some(PathBuf::from(CONFIG_FILE)),
+            None => None,
+        };
+        let mut file = match config {
+            Some(ref path) => try!(FileCfg::load(path)),
+            None => FileCfg::default(),
+        };
+        if resuming {
+            file.good = None;
+            file.bad = None;
+            file.regress = None;
+        }
+
+        let format = match matches.value_of("format") {
+            Some(format) => try!(format.parse()),
+            None => Format::default(),
+        };
+
+        let good: Option<Version> = match matches.value_of("good").or(as_str(&file.good)) {
+            Some(good) => Some(try!(good.parse())),
+            None => None,
+        };
+        let bad: Option<Version> = match matches.value_of("bad").or(as_str(&file.bad)) {
+            Some(bad) => Some(try!(bad.parse())),
+            None => None,
+        };
 
-        let bad = try!(matches.value_of("bad").ok_or("missing arg: `bad`"));
-        let bad: Nightly = try!(bad.parse());
+        let (good, bad) = resolve_releases(format, good, bad);
 
-        if bad.date < good.date {
-            return Err(Error::from("`bad` must be after `good`"));
+        let find_fix = matches.is_present("find-fix");
+        if let (Some(good), Some(bad)) = (good.as_ref(), bad.as_ref()) {
+            try!(check_order(good, bad, find_fix));
         }
 
-        let cmd = try!(matches.value_of_os("COMMAND").ok_or("missing arg: `COMMAND`"));
-        let args: Vec<_> = matches.values_of_os("ARGS")
-                                  .map(|args| args.collect())
-                                  .unwrap_or(Vec::new());
+        let bad_pattern = match matches.value_of("bad-if-output-matches") {
+            Some(pattern) => Some(try!(Regex::new(pattern))),
+            None => None,
+        };
+        let good_pattern = match matches.value_of("good-if-output-matches") {
+            Some(pattern) => Some(try!(Regex::new(pattern))),
+            None => None,
+        };
+
+        // `--regress` has a default, so the file's is only overridden if it was given explicitly.
+        let regress = match file.regress {
+            Some(ref regress) if matches.occurrences_of("regress") == 0 => try!(regress.parse()),
+            _ => {
+                match matches.value_of("regress") {
+                    Some(regress) => try!(regress.parse()),
+                    None => Regress::default(),
+                }
+            }
+        };
+
+        let secs = match matches.value_of("timeout") {
+            Some(secs) => Some(try!(secs.parse())),
+            None => file.timeout,
+        };
+        let timeout = match (secs, matches.value_of("timeout-verdict")) {
+            (Some(secs), verdict) => {
+                Some(Timeout {
+                    secs: secs,
+                    verdict: try!(verdict.unwrap_or("bad").parse()),
+                })
+            }
+            (None, Some(_)) => return Err(Error::from("`--timeout-verdict` needs a timeout")),
+            (None, None) => None,
+        };
+
+        let jobs = match matches.value_of("jobs") {
+            Some(jobs) => try!(jobs.parse()),
+            None => 1,
+        };
+        let runs = match matches.value_of("runs") {
+            Some(runs) => try!(runs.parse()),
+            None => 1,
+        };
+        let vote = match matches.value_of("vote") {
+            Some(vote) => try!(vote.parse()),
+            None => Vote::default(),
+        };
+
+        // Components in the file imply `--minimal`, like `--component` does.
+        let (minimal, names) = if matches.is_present("minimal") ||
+                                  matches.is_present("component") {
+            (true, strings(matches.values_of("component")))
+        } else {
+            (!file.components.is_empty(), file.components.clone())
+        };
+        let mut components = Vec::new();
+        if minimal {
+            components.extend(MINIMAL_COMPONENTS.iter().map(|&name| String::from(name)));
+        }
+        for name in names {
+            if !components.contains(&name) {
+                components.push(name);
+            }
+        }
+
+        let targets = match strings(matches.values_of("target")) {
+            ref targets if targets.is_empty() => file.targets.clone(),
+            targets => targets,
+        };
+
+        let (cmd, args) = if matches.is_present("COMMAND") || matches.is_present("rust-file") {
+            (matches.value_of_os("COMMAND").map(OsString::from),
+             matches.values_of_os("ARGS")
+                    .map(|args| args.map(OsString::from).collect())
+                    .unwrap_or(Vec::new()))
+        } else {
+            (file.command.as_ref().map(OsString::from),
+             file.args.iter().map(OsString::from).collect())
+        };
 
         Ok(Cfg {
             good: good,
             bad: bad,
+            refine: matches.is_present("refine"),
+            interactive: matches.is_present("interactive"),
+            no_verify: matches.is_present("no-verify"),
+            no_prefetch: matches.is_present("no-prefetch"),
+            keep_toolchains: matches.is_present("keep-toolchains"),
+            jobs: jobs,
+            runs: runs,
+            vote: vote,
+            probabilistic: matches.is_present("probabilistic"),
+            bad_pattern: bad_pattern,
+            good_pattern: good_pattern,
+            regress: regress,
+            timeout: timeout,
+            components: components,
+            targets: targets,
+            host: matches.value_of("host").map(String::from),
+            format: format,
+            output: matches.value_of_os("output").map(PathBuf::from),
+            log_dir: matches.value_of_os("log-dir").map(PathBuf::from),
+            quiet_steps: matches.is_present("quiet-steps"),
+            // Versions tested at once would otherwise build over each other.
+            isolate_target_dir: jobs > 1 || matches.is_present("isolate-target-dir"),
+            rust_file: matches.value_of_os("rust-file").map(PathBuf::from),
+            run_binary: matches.is_present("run"),
+            env: file.env.clone(),
+            find_fix: find_fix,
+            config: config,
             cmd: cmd,
             args: args,
         })
     }
 }
 
+fn as_str(s: &Option<String>) -> Option<&str> {
+    s.as_ref().map(|s| &**s)
+}
+
+fn strings<'a, I: IntoIterator<Item = &'a str>>(values: Option<I>) -> Vec<String> {
+    values.into_iter().flat_map(|values| values).map(String::from).collect()
+}
+
+/// Prints the settings for bisecting, once the config file and the command line are merged.
+pub fn print_cfg(cfg: &Cfg) {
+    match cfg.config {
+        Some(ref path) => progress!(cfg.format, "bisecting with settings from {}:", path.display()),
+        None => progress!(cfg.format, "bisecting with settings:"),
+    }
+    let show = |name: &str, value: &fmt::Display| progress!(cfg.format, "    {}: {}", name, value);
+    let list = |values: &[String]| values.join(", ");
+
+    match cfg.good {
+        Some(ref good) => show("good", good),
+        None => show("good", &"(an older nightly, to be found)"),
+    }
+    match cfg.bad {
+        Some(ref bad) => show("bad", bad),
+        None => show("bad", &"(the latest nightly)"),
+    }
+    if cfg.find_fix {
+        show("looking for", &"the first good version after `bad`");
+    }
+    if let Some(ref rust_file) = cfg.rust_file {
+        show(if cfg.run_binary {
+                 "compile and run"
+             } else {
+                 "compile"
+             },
+             &rust_file.display());
+    } else if let Some(ref cmd) = cfg.cmd {
+        let mut command = cmd.to_string_lossy().into_owned();
+        for arg in &cfg.args {
+            command.push(' ');
+            command.push_str(&arg.to_string_lossy());
+        }
+        show("command", &command);
+    }
+    show("regress", &cfg.regress);
+    if cfg.runs > 1 {
+        show("runs", &format!("{}, combined by `{}` vote", cfg.runs, cfg.vote));
+    }
+    if cfg.probabilistic {
+        show("search", &"probabilistic");
+    }
+    if let Some(timeout) = cfg.timeout {
+        show("timeout",
+             &format!("{} seconds, after which a version is {}",
+                      timeout.secs,
+                      timeout.verdict));
+    }
+    if let Some(ref host) = cfg.host {
+        show("host", host);
+    }
+    if !cfg.targets.is_empty() {
+        show("targets", &list(&cfg.targets));
+    }
+    if !cfg.components.is_empty() {
+        show("components", &list(&cfg.components));
+    }
+    for &(ref name, ref value) in &cfg.env {
+        show("env", &format!("{}={}", name, value));
+    }
+}
+
+/// Asks the user for a verdict on `version`. Returns `None` if they want to stop. The question
+/// goes to stderr with `Format::Json`, like other progress.
+pub fn ask_verdict(cfg: &Cfg, version: &Version) -> Result<Option<Verdict>> {
+    use std::io::{self, Write};
+    loop {
+        let question = format!("is {} good or bad? (good/bad/skip/quit) ", version);
+        if cfg.format == Format::Json {
+            try!(write!(io::stderr(), "{}", question));
+        } else {
+            print!("{}", question);
+            try!(io::stdout().flush());
+        }
+
+        let mut input = String::new();
+        if try!(io::stdin().read_line(&mut input)) == 0 {
+            return Ok(None);
+        }
+        match input.trim() {
+            "g" | "good" => return Ok(Some(Verdict::Good)),
+            "b" | "bad" => return Ok(Some(Verdict::Bad)),
+            "s" | "skip" => return Ok(Some(Verdict::Skip)),
+            "q" | "quit" => return Ok(None),
+            _ => progress!(cfg.format, "please answer good, bad, skip or quit"),
+        }
+    }
+}
+
+/// Prints `e` as an error, to stderr so that it doesn't get mixed up with a JSON report.
 pub fn display_error<E: fmt::Display>(e: E) -> Result<()> {
-    use std::io::Write;
-    if let Some(mut t) = term::stdout() {
+    use std::io::{self, Write};
+    if let Some(mut t) = term::stderr() {
         try!(t.fg(term::color::RED));
         try!(t.attr(term::Attr::Bold));
 
@@ -97,9 +589,9 @@ pub fn display_error<E: fmt::Display>(e: E) -> Result<()> {
 
         try!(t.reset());
     } else {
-        print!("error: ");
+        try!(write!(io::stderr(), "error: "));
     }
-    println!("{}", e);
+    try!(writeln!(io::stderr(), "{}", e));
 
     Ok(())
 }
@@ -0,0 +1,168 @@
+//! Settings read from a `bisect.toml` file, for bisections that are rerun often.
+//!
+//! ```toml
+//! good = "nightly-2015-10-27"
+//! bad = "nightly-2015-11-30"
+//! command = "cargo"
+//! args = ["build"]
+//! regress = "error"
+//! timeout = 300
+//! targets = ["i686-unknown-linux-musl"]
+//! components = ["rust-src"]
+//!
+//! [env]
+//! RUST_BACKTRACE = "1"
+//! ```
+//!
+//! Everything is optional, and anything given on the command line takes precedence.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use toml;
+
+use {Error, Result};
+
+/// The config file that is read from the current directory if there is one.
+pub const CONFIG_FILE: &'static str = "bisect.toml";
+
+#[derive(Debug, Default)]
+pub struct FileCfg {
+    pub good: Option<String>,
+    pub bad: Option<String>,
+    pub command: Option<String>,
+    pub args: Vec<String>,
+    pub regress: Option<String>,
+    pub timeout: Option<u64>,
+    pub targets: Vec<String>,
+    pub components: Vec<String>,
+    pub env: Vec<(String, String)>,
+}
+
+impl FileCfg {
+    pub fn load(path: &Path) -> Result<FileCfg> {
+        let mut data = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut data));
+
+        let mut parser = toml::Parser::new(&data);
+        let table = match parser.parse() {
+            Some(table) => table,
+            None => {
+                let error = &parser.errors[0];
+                let (line, col) = parser.to_linecol(error.lo);
+                return Err(Error::from(format!("{}:{}:{}: {}",
+                                               path.display(),
+                                               line + 1,
+                                               col + 1,
+                                               error.desc)));
+            }
+        };
+
+        let mut cfg = FileCfg::default();
+        for (key, value) in table {
+            match &*key {
+                "good" => cfg.good = Some(try!(string(&key, value))),
+                "bad" => cfg.bad = Some(try!(string(&key, value))),
+                "command" => cfg.command = Some(try!(string(&key, value))),
+                "args" => cfg.args = try!(strings(&key, value)),
+                "regress" => cfg.regress = Some(try!(string(&key, value))),
+                "timeout" => {
+                    cfg.timeout = match value {
+                        toml::Value::Integer(secs) if secs > 0 => Some(secs as u64),
+                        _ => return Err(invalid(&key, "a positive number of seconds")),
+                    }
+                }
+                "targets" => cfg.targets = try!(strings(&key, value)),
+                "components" => cfg.components = try!(strings(&key, value)),
+                "env" => {
+                    let vars = match value {
+                        toml::Value::Table(vars) => vars,
+                        _ => return Err(invalid(&key, "a table")),
+                    };
+                    for (name, value) in vars {
+                        let value = try!(string(&format!("env.{}", name), value));
+                        cfg.env.push((name, value));
+                    }
+                }
+                _ => {
+                    return Err(Error::from(format!("unknown setting `{}` in {}",
+                                                   key,
+                                                   path.display())))
+                }
+            }
+        }
+        Ok(cfg)
+    }
+}
+
+fn string(key: &str, value: toml::Value) -> Result<String> {
+    match value {
+        toml::Value::String(s) => Ok(s),
+        _ => Err(invalid(key, "a string")),
+    }
+}
+
+fn strings(key: &str, value: toml::Value) -> Result<Vec<String>> {
+    match value {
+        toml::Value::Array(values) => values.into_iter().map(|value| string(key, value)).collect(),
+        _ => Err(invalid(key, "an array of strings")),
+    }
+}
+
+fn invalid(key: &str, expected: &str) -> Error {
+    Error::from(format!("`{}` must be {}", key, expected))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+
+    use super::*;
+    use Result;
+
+    /// Loads `contents` as a config file called `name`.
+    fn load(name: &str, contents: &str) -> Result<FileCfg> {
+        let path = env::temp_dir().join(name);
+        try!(try!(File::create(&path)).write_all(contents.as_bytes()));
+        let cfg = FileCfg::load(&path);
+        try!(fs::remove_file(&path));
+        cfg
+    }
+
+    #[test]
+    fn test_load() {
+        let cfg = load("rust-bisect-test-config.toml",
+                       r#"
+good = "nightly-2015-10-27"
+command = "cargo"
+args = ["build", "--release"]
+timeout = 300
+targets = ["i686-unknown-linux-musl"]
+
+[env]
+RUST_BACKTRACE = "1"
+"#)
+                      .unwrap();
+        assert_eq!(Some(String::from("nightly-2015-10-27")), cfg.good);
+        assert_eq!(None, cfg.bad);
+        assert_eq!(Some(String::from("cargo")), cfg.command);
+        assert_eq!(vec![String::from("build"), String::from("--release")], cfg.args);
+        assert_eq!(Some(300), cfg.timeout);
+        assert_eq!(vec![String::from("i686-unknown-linux-musl")], cfg.targets);
+        assert!(cfg.components.is_empty());
+        assert_eq!(vec![(String::from("RUST_BACKTRACE"), String::from("1"))], cfg.env);
+    }
+
+    #[test]
+    fn test_load_invalid() {
+        let name = "rust-bisect-test-config-invalid.toml";
+        assert!(load(name, "good = ").is_err());
+        assert!(load(name, "timeout = 0").is_err());
+        assert!(load(name, "args = \"build\"").is_err());
+        assert!(load(name, "[env]\nRUST_BACKTRACE = 1").is_err());
+        assert!(load(name, "jobs = 4").is_err());
+    }
+}
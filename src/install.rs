@@ -0,0 +1,125 @@
+//! Installing nightlies component by component, using their v2 manifests.
+//!
+//! A full install of the `rust` package includes things like the docs that bisecting rarely
+//! needs, and leaves out the standard libraries for cross-compiling. The manifest lists each
+//! component separately, so the ones that aren't wanted can be left out of the download and the
+//! ones for other targets added.
+
+use std::fs::File;
+use std::io::Read;
+
+use multirust;
+use rust_install::dist::{self, DownloadCfg, ToolchainDesc};
+use rust_install::manifest::{Changes, Manifestation};
+use rust_manifest::{Component, Manifest};
+
+use {Error, Result};
+
+/// The components installed with `--minimal`, before any added with `--component`.
+pub const MINIMAL_COMPONENTS: &'static [&'static str] = &["rustc", "rust-std", "cargo"];
+
+/// Installs the nightly described by `desc` into `toolchain`: just the named `components` if there
+/// are any, or else all of the `rust` package's, along with the standard library for each of
+/// `targets`.
+pub fn install_from_manifest(mr_cfg: &multirust::Cfg,
+                             toolchain: &multirust::Toolchain,
+                             desc: &ToolchainDesc,
+                             components: &[String],
+                             targets: &[String])
+                             -> Result<()> {
+    let triple = try!(desc.target_triple().ok_or("unsupported host"));
+    let mut manifest = try!(download_manifest(mr_cfg, desc));
+    {
+        let rust = try!(try!(manifest.get_package("rust")).get_target(&triple));
+        if !rust.available {
+            return Err(Error::from(format!("no rust package available for {}", triple)));
+        }
+    }
+    if !components.is_empty() {
+        manifest = try!(select_components(manifest, &triple, components));
+    }
+    let changes = Changes {
+        add_extensions: try!(target_extensions(&manifest, &triple, targets)),
+        remove_extensions: Vec::new(),
+    };
+
+    let manifestation = try!(Manifestation::open(toolchain.prefix().clone(), &triple));
+    try!(manifestation.update(&manifest,
+                              changes,
+                              &mr_cfg.temp_cfg,
+                              ntfy!(&mr_cfg.notify_handler)));
+    Ok(())
+}
+
+fn download_manifest(mr_cfg: &multirust::Cfg, desc: &ToolchainDesc) -> Result<Manifest> {
+    let url = format!("{}.toml", desc.manifest_url(&mr_cfg.dist_root_url));
+    let download_cfg = DownloadCfg {
+        dist_root: &mr_cfg.dist_root_url,
+        temp_cfg: &mr_cfg.temp_cfg,
+        notify_handler: ntfy!(&mr_cfg.notify_handler),
+    };
+    // Without an update hash to compare against, the manifest is always downloaded.
+    let (file, _) = try!(try!(dist::download_and_check(&url, None, ".toml", download_cfg))
+                             .ok_or("manifest was not downloaded"));
+
+    let mut data = String::new();
+    try!(try!(File::open(&*file)).read_to_string(&mut data));
+    Ok(try!(Manifest::parse(&data)))
+}
+
+/// Narrows the components of the `rust` package for `triple` down to the named ones.
+/// `Manifestation::update` installs all of the components the manifest lists as required, so this
+/// makes those exactly the ones wanted.
+fn select_components(mut manifest: Manifest, triple: &str, names: &[String]) -> Result<Manifest> {
+    let wanted = {
+        let rust = try!(try!(manifest.get_package("rust")).get_target(triple));
+        let mut wanted: Vec<Component> = Vec::new();
+        for name in names {
+            // Components that aren't specific to a target, like rust-src, have a target of `*`.
+            let component = rust.components
+                                .iter()
+                                .chain(&rust.extensions)
+                                .find(|c| {
+                                    c.pkg == *name && (c.target == triple || c.target == "*")
+                                });
+            match component {
+                Some(component) => wanted.push(component.clone()),
+                None => return Err(Error::from(format!("no {} component for {}", name, triple))),
+            }
+        }
+        wanted
+    };
+
+    let rust = manifest.packages
+                       .get_mut("rust")
+                       .and_then(|rust| rust.targets.get_mut(triple))
+                       .expect("rust package was just found");
+    rust.components = wanted;
+    Ok(manifest)
+}
+
+/// Finds the `rust-std` extensions for each of `targets`. It is an error for one to be missing
+/// from the manifest, or to be listed but not available, as when its build failed.
+fn target_extensions(manifest: &Manifest,
+                     triple: &str,
+                     targets: &[String])
+                     -> Result<Vec<Component>> {
+    let rust = try!(try!(manifest.get_package("rust")).get_target(triple));
+    let std = try!(manifest.get_package("rust-std"));
+    let mut extensions = Vec::new();
+    for target in targets {
+        // The host's standard library is installed as one of the components.
+        if target == triple {
+            continue;
+        }
+        let available = std.get_target(target).map(|std| std.available).unwrap_or(false);
+        let extension = rust.extensions
+                            .iter()
+                            .find(|c| c.pkg == "rust-std" && c.target == *target);
+        match extension {
+            Some(extension) if available => extensions.push(extension.clone()),
+            _ => return Err(Error::from(format!("no rust-std available for {}", target))),
+        }
+    }
+    Ok(extensions)
+}
@@ -1,4 +1,6 @@
-#[macro_use(shared_ntfy)]
+#![feature(process_exec)]
+
+#[macro_use(ntfy, shared_ntfy)]
 extern crate rust_install;
 
 extern crate chrono;
@@ -6,38 +8,112 @@ extern crate clap;
 extern crate hyper;
 extern crate libc;
 extern crate multirust;
+extern crate regex;
+extern crate rust_manifest;
+extern crate rustc_serialize;
+extern crate semver;
 extern crate term;
+extern crate toml;
 
-use std::{error, fmt, str};
-use std::ffi::OsStr;
+use std::{env, error, fmt, fs, str};
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use std::process::{self, Child, Output, Stdio};
+use std::sync::Arc;
+#[cfg(unix)]
+use std::sync::{ONCE_INIT, Once, mpsc};
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(unix)]
+use std::sync::atomic::{ATOMIC_BOOL_INIT, ATOMIC_USIZE_INIT, AtomicUsize};
+use std::thread;
+use std::time;
 
-use chrono::NaiveDate;
-use hyper::client::Client;
-use rust_install::dist::ToolchainDesc;
+use chrono::{Duration, NaiveDate};
+use regex::Regex;
+use rust_install::dist::{self, ToolchainDesc};
 
 const NIGHTLY: &'static str = "nightly";
 
+/// Exit code with which a command marks a nightly as untestable, as with `git bisect run`.
+pub const SKIP_EXIT_CODE: i32 = 125;
+
+/// How far back from `bad` to look for a good nightly when none is given, about five years.
+const GOOD_SEARCH_MAX_WEEKS: i64 = 256;
+
+/// Exit code of rustc when it panics.
+const ICE_EXIT_CODE: i32 = 101;
+
+/// How often a flaky command is assumed to give the wrong verdict, with `--probabilistic`.
+const FLAKE_RATE: f64 = 0.1;
+
+/// How likely `--probabilistic` has to find a version to be the first bad one before stopping.
+const CONFIDENCE: f64 = 0.95;
+
+/// Where to compare commits in the Rust repository.
+const RUST_REPO_URL: &'static str = "https://github.com/rust-lang/rust";
+
+/// Directory for rust-bisect's own files, relative to where it is run.
+const WORK_DIR: &'static str = ".rust-bisect";
+
 pub type Error = Box<error::Error>;
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Prints progress like `println!`, but to stderr when the format is `Format::Json`, since stdout
+/// is kept for the report then.
+macro_rules! progress {
+    ($format:expr, $($arg:tt)*) => {
+        if $format == ::Format::Json {
+            use std::io::Write;
+            let _ = writeln!(::std::io::stderr(), $($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    }
+}
+
 mod bisect;
-pub use bisect::least_satisfying;
+pub use bisect::{Satisfies, least_satisfying, least_satisfying_k};
 
 pub mod cli;
 
-#[derive(Clone, Copy, Debug)]
+mod config;
+
+mod install;
+
+mod logs;
+
+mod nightlies;
+
+mod release;
+pub use release::Release;
+
+mod results;
+pub use results::Format;
+use results::Results;
+
+mod session;
+use session::{Session, Step};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Nightly {
     pub date: NaiveDate,
 }
 
 impl Nightly {
-    fn to_toolchain_desc(&self) -> ToolchainDesc {
+    /// Describes the toolchain for this nightly built for `host`, or for the native host if `host`
+    /// is `None`.
+    fn to_toolchain_desc(&self, host: Option<&str>) -> ToolchainDesc {
+        // `--host` is checked to parse this way before it gets here.
+        let host = host.and_then(|host| ToolchainDesc::from_str(&format!("{}-{}", host, NIGHTLY)));
         ToolchainDesc {
             date: Some(self.date.to_string()),
             channel: String::from(NIGHTLY),
-            arch: None,
-            os: None,
-            env: None,
+            arch: host.as_ref().and_then(|host| host.arch.clone()),
+            os: host.as_ref().and_then(|host| host.os.clone()),
+            env: host.as_ref().and_then(|host| host.env.clone()),
         }
     }
 }
@@ -48,9 +124,17 @@ impl From<NaiveDate> for Nightly {
     }
 }
 
+/// Accepts `nightly-YYYY-MM-DD`, a bare date, or a date relative to today as understood by
+/// `relative_date`.
 impl str::FromStr for Nightly {
     type Err = Error;
     fn from_str(s: &str) -> Result<Nightly> {
+        if let Some(date) = relative_date(s, chrono::UTC::today().naive_utc()) {
+            return Ok(Nightly { date: date });
+        }
+        if let Ok(date) = s.parse() {
+            return Ok(Nightly { date: date });
+        }
         let desc = try!(ToolchainDesc::from_str(s).ok_or("invalid toolchain name"));
         if desc.channel != NIGHTLY || desc.date.is_none() {
             return Err(Error::from("not a dated nightly"));
@@ -65,75 +149,1673 @@ impl fmt::Display for Nightly {
     }
 }
 
-#[derive(Debug)]
-pub struct Cfg<'a> {
-    pub good: Nightly,
-    pub bad: Nightly,
-    pub cmd: &'a OsStr,
-    pub args: Vec<&'a OsStr>,
-}
-
-fn list_available_nightlies(dist_root: &str,
-                            from: NaiveDate,
-                            to: NaiveDate)
-                            -> Result<Vec<Nightly>> {
-    assert!(from < to, "`from` must be less than `to`");
-    let client = Client::new();
-    let mut nightlies = Vec::with_capacity((to - from).num_days() as usize);
-    let mut date = from;
-    while date < to {
-        let nightly = Nightly::from(date);
-        let manifest_url = nightly.to_toolchain_desc().manifest_url(dist_root);
-        let resp = try!(client.head(&manifest_url).send());
-        // TODO: ensure failures are 404
-        if resp.status.is_success() {
-            nightlies.push(nightly);
-        }
-        date = date.succ();
-    }
-    Ok(nightlies)
-}
-
-pub fn run<'a>(cfg: &'a Cfg, mr_cfg: &multirust::Cfg) -> Result<i32> {
-
-    println!("finding available nightlies between {} and {}",
-             cfg.good,
-             cfg.bad);
-    let nightlies = try!(list_available_nightlies(&*mr_cfg.dist_root_url,
-                                                  cfg.good.date,
-                                                  cfg.bad.date));
-    if nightlies.is_empty() {
-        try!(cli::display_error(format!("no nightlies found between {} and {}",
-                                        cfg.good,
-                                        cfg.bad)));
+/// Resolves `today`, `yesterday`, `last-week`, or a number of days or weeks ago like `-30d` or
+/// `-2w`, to a date.
+fn relative_date(s: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let days_ago = match s {
+        "today" => 0,
+        "yesterday" => 1,
+        "last-week" => 7,
+        _ if s.starts_with('-') => {
+            let mut chars = s[1..].chars();
+            let unit = chars.next_back();
+            // Only digits, since `parse` would also take a sign, making `--5d` a future date.
+            let count = chars.as_str();
+            if count.is_empty() || !count.chars().all(|c| c.is_digit(10)) {
+                return None;
+            }
+            let count: u32 = match count.parse() {
+                Ok(count) => count,
+                Err(_) => return None,
+            };
+            match unit {
+                Some('d') => count as i64,
+                Some('w') => count as i64 * 7,
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    today.checked_sub(Duration::days(days_ago))
+}
+
+/// A toolchain to bisect across: either a dated nightly or a stable release.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Version {
+    Nightly(Nightly),
+    Release(Release),
+}
+
+impl Version {
+    /// The name of the toolchain for this version, built for `cfg.host`.
+    fn toolchain_name(&self, cfg: &Cfg) -> String {
+        match (self, &cfg.host) {
+            (&Version::Nightly(ref nightly), host) => {
+                nightly.to_toolchain_desc(host.as_ref().map(|host| &**host)).to_string()
+            }
+            (&Version::Release(ref release), &Some(ref host)) => format!("{}-{}", release, host),
+            (&Version::Release(ref release), &None) => release.to_string(),
+        }
+    }
+
+    /// Gets the toolchain for this version, installing it first if needed. Nightlies are installed
+    /// from their manifests when `cfg` asks for particular components or targets; otherwise the
+    /// toolchain name, which includes any host, is enough for multirust-rs to install it.
+    fn install<'a>(&self,
+                   cfg: &Cfg,
+                   mr_cfg: &'a multirust::Cfg)
+                   -> Result<multirust::Toolchain<'a>> {
+        let toolchain = try!(mr_cfg.get_toolchain(&self.toolchain_name(cfg), true));
+        match *self {
+            Version::Nightly(_) if cfg.components.is_empty() && cfg.targets.is_empty() => {
+                try!(toolchain.install_from_dist_if_not_installed())
+            }
+            Version::Nightly(ref nightly) => {
+                if !toolchain.exists() {
+                    let host = cfg.host.as_ref().map(|host| &**host);
+                    try!(install::install_from_manifest(mr_cfg,
+                                                        &toolchain,
+                                                        &nightly.to_toolchain_desc(host),
+                                                        &cfg.components,
+                                                        &cfg.targets));
+                }
+            }
+            // Releases aren't toolchain names multirust-rs knows how to install from dist, so
+            // install them from their installer instead.
+            Version::Release(ref release) => {
+                if !toolchain.exists() {
+                    let host = match cfg.host {
+                        Some(ref host) => host.clone(),
+                        None => try!(host_triple()),
+                    };
+                    let url = release.installer_url(&mr_cfg.dist_root_url, &host);
+                    try!(toolchain.install_from_installers(&[OsStr::new(&url)]));
+                }
+            }
+        }
+        Ok(toolchain)
+    }
+}
+
+impl str::FromStr for Version {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Version> {
+        match s.parse() {
+            Ok(release) => Ok(Version::Release(release)),
+            Err(_) => s.parse().map(Version::Nightly),
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Version::Nightly(ref nightly) => write!(f, "{}", nightly),
+            Version::Release(ref release) => write!(f, "{}", release),
+        }
+    }
+}
+
+fn host_triple() -> Result<String> {
+    let (arch, os, env) = dist::get_host_triple();
+    let os = try!(os.ok_or("unsupported host"));
+    Ok(match env {
+        Some(env) => format!("{}-{}-{}", arch, os, env),
+        None => format!("{}-{}", arch, os),
+    })
+}
+
+/// A verdict on a tested version, in `git bisect` terms.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Good,
+    Bad,
+    Skip,
+}
+
+impl str::FromStr for Verdict {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Verdict> {
+        match s {
+            "good" => Ok(Verdict::Good),
+            "bad" => Ok(Verdict::Bad),
+            "skip" => Ok(Verdict::Skip),
+            _ => Err(Error::from(format!("invalid verdict: {}", s))),
+        }
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Verdict::Good => "good",
+            Verdict::Bad => "bad",
+            Verdict::Skip => "skip",
+        })
+    }
+}
+
+/// The kind of change being bisected for, which decides what makes a version bad.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Regress {
+    /// The command started failing.
+    Error,
+    /// The command started succeeding, for example when something started compiling.
+    Success,
+    /// rustc started hitting an internal compiler error.
+    Ice,
+    /// The command started failing with an error other than an internal compiler error.
+    NonIce,
+}
+
+impl Regress {
+    fn is_bad(self, outcome: &Outcome) -> bool {
+        let success = outcome.output.status.success();
+        match self {
+            Regress::Error => !success,
+            Regress::Success => success,
+            Regress::Ice => is_ice(outcome),
+            Regress::NonIce => !success && !is_ice(outcome),
+        }
+    }
+}
+
+impl Default for Regress {
+    fn default() -> Regress {
+        Regress::Error
+    }
+}
+
+impl str::FromStr for Regress {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Regress> {
+        match s {
+            "error" => Ok(Regress::Error),
+            "success" => Ok(Regress::Success),
+            "ice" => Ok(Regress::Ice),
+            "non-ice" => Ok(Regress::NonIce),
+            _ => Err(Error::from(format!("invalid regression kind: {}", s))),
+        }
+    }
+}
+
+/// How the verdicts from running the command several times on a version, with `--runs`, are
+/// combined into one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Vote {
+    /// The version is bad if any run was bad.
+    AnyFail,
+    /// The version is bad if more runs were bad than good, and skipped on a tie.
+    Majority,
+    /// The version is bad only if every run was bad.
+    AllFail,
+}
+
+impl Vote {
+    /// Combines the verdicts of several runs. Skipped runs don't count, unless all of them were.
+    fn tally(self, verdicts: &[Verdict]) -> Verdict {
+        let bad = verdicts.iter().filter(|&&verdict| verdict == Verdict::Bad).count();
+        let good = verdicts.iter().filter(|&&verdict| verdict == Verdict::Good).count();
+        if bad + good == 0 {
+            return Verdict::Skip;
+        }
+        match self {
+            Vote::Majority if bad == good => Verdict::Skip,
+            Vote::AnyFail if bad > 0 => Verdict::Bad,
+            Vote::Majority if bad > good => Verdict::Bad,
+            Vote::AllFail if good == 0 => Verdict::Bad,
+            _ => Verdict::Good,
+        }
+    }
+}
+
+impl Default for Vote {
+    fn default() -> Vote {
+        Vote::AnyFail
+    }
+}
+
+impl str::FromStr for Vote {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Vote> {
+        match s {
+            "any-fail" => Ok(Vote::AnyFail),
+            "majority" => Ok(Vote::Majority),
+            "all-fail" => Ok(Vote::AllFail),
+            _ => Err(Error::from(format!("invalid vote: {}", s))),
+        }
+    }
+}
+
+impl fmt::Display for Vote {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Vote::AnyFail => "any-fail",
+            Vote::Majority => "majority",
+            Vote::AllFail => "all-fail",
+        })
+    }
+}
+
+impl fmt::Display for Regress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Regress::Error => "error",
+            Regress::Success => "success",
+            Regress::Ice => "ice",
+            Regress::NonIce => "non-ice",
+        })
+    }
+}
+
+/// A limit on how long the command may run for each step.
+#[derive(Clone, Copy, Debug)]
+pub struct Timeout {
+    pub secs: u64,
+    /// The verdict for a version with which the command times out.
+    pub verdict: Verdict,
+}
+
+#[derive(Clone, Debug)]
+pub struct Cfg {
+    pub good: Option<Version>,
+    pub bad: Option<Version>,
+    pub refine: bool,
+    /// Ask the user for each verdict, running `cmd` (if given) only to help them decide.
+    pub interactive: bool,
+    /// Don't check that `good` is good and `bad` is bad before bisecting.
+    pub no_verify: bool,
+    /// Don't install the versions that might be tested next while the current one is tested.
+    pub no_prefetch: bool,
+    /// Leave the toolchains that were installed in the bisect store once bisecting is done.
+    pub keep_toolchains: bool,
+    /// How many versions to test at once. Anything below 2 tests them one at a time.
+    pub jobs: usize,
+    /// Mark a version as bad if the command's output matches, whatever its exit code.
+    pub bad_pattern: Option<Regex>,
+    /// Mark a version as good if the command's output matches, whatever its exit code.
+    pub good_pattern: Option<Regex>,
+    pub regress: Regress,
+    pub timeout: Option<Timeout>,
+    /// The components to install for each nightly, instead of the whole `rust` package. Releases
+    /// are always installed whole.
+    pub components: Vec<String>,
+    /// Targets to install the standard library for on top of the host's. Nightlies without one
+    /// of them are skipped.
+    pub targets: Vec<String>,
+    /// The host to bisect toolchains for, instead of the native one. Nightlies without a build for
+    /// it are skipped.
+    pub host: Option<String>,
+    pub format: Format,
+    /// Where to save the results as JSON once bisecting is done.
+    pub output: Option<PathBuf>,
+    /// Where to save the output of each step, and what it was run with.
+    pub log_dir: Option<PathBuf>,
+    /// Only echo the command's output when it fails; the rest only goes to `log_dir`.
+    pub quiet_steps: bool,
+    /// Give each version a `CARGO_TARGET_DIR` of its own, so that builds with one don't get in
+    /// the way of builds with another.
+    pub isolate_target_dir: bool,
+    /// A single-file program to compile with each version, instead of running `cmd`.
+    pub rust_file: Option<PathBuf>,
+    /// Run the program compiled from `rust_file`, rather than just compiling it.
+    pub run_binary: bool,
+    /// Variables to set in the command's environment.
+    pub env: Vec<(String, String)>,
+    /// How many times to run the command on each version.
+    pub runs: usize,
+    /// How the verdicts of the runs are combined when there are several.
+    pub vote: Vote,
+    /// Weigh verdicts against each other rather than taking each as final.
+    pub probabilistic: bool,
+    /// Look for the first version where the command starts succeeding, from a bad `bad` to a good
+    /// `good` after it.
+    pub find_fix: bool,
+    /// The config file that settings not given on the command line came from.
+    pub config: Option<PathBuf>,
+    pub cmd: Option<OsString>,
+    pub args: Vec<OsString>,
+}
+
+/// Not derived, since the command has to be run at least once on each version.
+impl Default for Cfg {
+    fn default() -> Cfg {
+        Cfg {
+            good: None,
+            bad: None,
+            refine: false,
+            interactive: false,
+            no_verify: false,
+            no_prefetch: false,
+            keep_toolchains: false,
+            jobs: 1,
+            bad_pattern: None,
+            good_pattern: None,
+            regress: Regress::default(),
+            timeout: None,
+            components: Vec::new(),
+            targets: Vec::new(),
+            host: None,
+            format: Format::default(),
+            output: None,
+            log_dir: None,
+            quiet_steps: false,
+            isolate_target_dir: false,
+            rust_file: None,
+            run_binary: false,
+            env: Vec::new(),
+            runs: 1,
+            vote: Vote::default(),
+            probabilistic: false,
+            find_fix: false,
+            config: None,
+            cmd: None,
+            args: Vec::new(),
+        }
+    }
+}
+
+impl Cfg {
+    /// Whether there is something to run for each version, either `cmd` or `rust_file`.
+    fn has_command(&self) -> bool {
+        self.cmd.is_some() || self.rust_file.is_some()
+    }
+
+    /// Whether the command's output needs to be captured, to decide on a verdict, to save it, or
+    /// to keep it off stdout when that is kept for the JSON report.
+    fn captures_output(&self) -> bool {
+        self.bad_pattern.is_some() || self.good_pattern.is_some() ||
+        self.regress == Regress::Ice || self.regress == Regress::NonIce ||
+        self.log_dir.is_some() || self.format == Format::Json
+    }
+}
+
+/// Loads the multirust configuration from the environment, but with toolchains kept in a store of
+/// rust-bisect's own, apart from the ones installed by hand.
+pub fn load_mr_cfg(notify_handler: multirust::SharedNotifyHandler) -> Result<multirust::Cfg> {
+    let mut mr_cfg = try!(multirust::Cfg::from_env(notify_handler));
+    let store = mr_cfg.multirust_dir.join("bisect");
+    mr_cfg.toolchains_dir = store.join("toolchains");
+    // Update hashes are kept by toolchain name, so they would clash too.
+    mr_cfg.update_hash_dir = store.join("update-hashes");
+    Ok(mr_cfg)
+}
+
+fn session_path() -> PathBuf {
+    Path::new(WORK_DIR).join("session")
+}
+
+/// The `CARGO_TARGET_DIR` for building with `version` when `cfg.isolate_target_dir` is set.
+fn target_dir(cfg: &Cfg, version: &Version) -> PathBuf {
+    Path::new(WORK_DIR).join("target").join(version.toolchain_name(cfg))
+}
+
+/// Bisects from scratch, saving progress to a new session as it goes.
+///
+/// If `bad` isn't given, the latest nightly is used. If `good` isn't given, ever older nightlies
+/// are tested until a good one is found. When looking for a fix, `good` is the newer endpoint and
+/// defaults to the latest nightly instead, and `bad` has to be given.
+pub fn run(cfg: &Cfg, mr_cfg: &multirust::Cfg) -> Result<i32> {
+    if !cfg.has_command() && !cfg.interactive {
+        try!(cli::display_error("no command given, on the command line or in a config file"));
         return Ok(libc::EXIT_FAILURE);
     }
+    let (good, bad) = try!(endpoints(cfg, mr_cfg));
+    let cfg = &Cfg { good: good.clone(), bad: Some(bad.clone()), ..cfg.clone() };
+    cli::print_cfg(cfg);
+    let (good, bad) = match good {
+        Some(good) => (good, bad),
+        None => {
+            let bad = match bad {
+                Version::Nightly(bad) => bad,
+                Version::Release(_) => {
+                    return Err(Error::from("`good` can only be left out when `bad` is a nightly"))
+                }
+            };
+            match try!(find_good(cfg, mr_cfg, bad)) {
+                Check::Passed((good, bad)) => (Version::Nightly(good), Version::Nightly(bad)),
+                check => return Ok(check.exit_code(cfg)),
+            }
+        }
+    };
+    if cfg.good.is_some() && !cfg.no_verify {
+        match try!(verify_endpoints(cfg, mr_cfg, &good, &bad)) {
+            Check::Passed(()) => {}
+            check => return Ok(check.exit_code(cfg)),
+        }
+    }
+    match try!(start_session(cfg, mr_cfg, good, bad)) {
+        Some(session) => bisect(cfg, mr_cfg, session),
+        None => Ok(libc::EXIT_FAILURE),
+    }
+}
 
-    println!("bisecting across {} nightlies (about {} steps)",
-             nightlies.len(),
-             nightlies.len().next_power_of_two().trailing_zeros());
+/// Starts a new session without testing anything, so that verdicts can be given by hand.
+pub fn start(cfg: &Cfg, mr_cfg: &multirust::Cfg) -> Result<i32> {
+    let (good, bad) = try!(endpoints(cfg, mr_cfg));
+    let good = try!(good.ok_or("no `good` version given, on the command line or in a config file"));
+    cli::print_cfg(&Cfg { good: Some(good.clone()), bad: Some(bad.clone()), ..cfg.clone() });
+    match try!(start_session(cfg, mr_cfg, good, bad)) {
+        Some(session) => next_step(&session, mr_cfg),
+        None => Ok(libc::EXIT_FAILURE),
+    }
+}
 
-    let idx = least_satisfying(&nightlies[..], |nightly| {
-        println!("testing with {}", nightly);
+/// Continues the current session, running the command for each remaining step. The command, or
+/// Rust file, saved with the session is used unless `cfg` has one, and likewise for whether to
+/// isolate target dirs. Everything else saved with the session always comes from it, and giving
+/// a different host, targets, components or environment is an error.
+pub fn resume(cfg: &Cfg, mr_cfg: &multirust::Cfg) -> Result<i32> {
+    let session = try!(Session::load(&session_path()));
+    let mut cfg = cfg.clone();
+    if !cfg.has_command() {
+        if session.cmd.is_none() && session.rust_file.is_none() && !cfg.interactive {
+            try!(cli::display_error("no command given, and the session doesn't have one"));
+            return Ok(libc::EXIT_FAILURE);
+        }
+        cfg.cmd = session.cmd.clone();
+        cfg.args = session.args.clone();
+        cfg.rust_file = session.rust_file.clone();
+        cfg.run_binary = session.run_binary;
+    }
+    // Other toolchains would give verdicts that don't go with the session's, and the ones
+    // installed for it would be left behind.
+    let mismatches = [("components", !cfg.components.is_empty() &&
+                                     cfg.components != session.components),
+                      ("targets", !cfg.targets.is_empty() && cfg.targets != session.targets),
+                      ("host", cfg.host.is_some() && cfg.host != session.host),
+                      ("env", !cfg.env.is_empty() && cfg.env != session.env)];
+    for &(name, mismatched) in &mismatches {
+        if mismatched {
+            try!(cli::display_error(format!("the session was started with different {}; start a \
+                                             new one to change them",
+                                            name)));
+            return Ok(libc::EXIT_FAILURE);
+        }
+    }
+    cfg.components = session.components.clone();
+    cfg.targets = session.targets.clone();
+    cfg.host = session.host.clone();
+    cfg.env = session.env.clone();
+    // The session's verdicts must all mean the same thing, so what decides them can't change.
+    cfg.regress = session.regress;
+    cfg.bad_pattern = session.bad_pattern.clone();
+    cfg.good_pattern = session.good_pattern.clone();
+    cfg.timeout = session.timeout;
+    cfg.runs = session.runs;
+    cfg.vote = session.vote;
+    if !cfg.isolate_target_dir {
+        cfg.isolate_target_dir = session.isolate_target_dir;
+    }
+    cfg.good = Some(session.good.clone());
+    cfg.bad = Some(session.bad.clone());
+    cfg.find_fix = session.find_fix;
+    cfg.probabilistic = session.probabilistic;
+    cli::print_cfg(&cfg);
+    bisect(&cfg, mr_cfg, session)
+}
 
-        let toolchain = mr_cfg.get_toolchain(&nightly.to_string(), false)
-                              .expect("could not get toolchain");
-        toolchain.install_from_dist_if_not_installed().expect("could not install toolchain");
+/// Records a verdict for `version` in the current session, or for the version to be tested next
+/// if none is given.
+pub fn mark(verdict: Verdict, version: Option<Version>, mr_cfg: &multirust::Cfg) -> Result<i32> {
+    let path = session_path();
+    let mut session = try!(Session::load(&path));
+    let version = match version.or_else(|| session.next().cloned()) {
+        Some(version) => version,
+        None => {
+            try!(cli::display_error("the bisection is already over"));
+            return Ok(libc::EXIT_FAILURE);
+        }
+    };
 
-        let mut cmd = toolchain.create_command(cfg.cmd).expect("could not create command");
-        cmd.args(&cfg.args);
-        let res = cmd.status().expect("could not run command").success();
+    try!(session.record(Step::without_command(version, verdict)));
+    try!(session.save(&path));
+    next_step(&session, mr_cfg)
+}
 
-        println!("command {} with {}",
-                 if res {
-                     "succeeded"
-                 } else {
-                     "failed"
-                 },
-                 nightly);
-        !res
-    });
+/// Shows the progress of the current session.
+pub fn status() -> Result<i32> {
+    let session = try!(Session::load(&session_path()));
+    println!("bisecting between {} and {}{}",
+             session.older(),
+             session.newer(),
+             if session.find_fix {
+                 ", looking for the fix"
+             } else {
+                 ""
+             });
+    for step in &session.steps {
+        println!("{} is {}", step.version, step.verdict);
+    }
 
-    println!("{} is the first failing nightly", nightlies[idx]);
+    match session.next() {
+        Some(version) => {
+            let remaining = session.remaining();
+            println!("{} left to test (about {} steps), starting with {}",
+                     remaining,
+                     estimate_steps(remaining),
+                     version);
+        }
+        None => report(&session),
+    }
+    Ok(libc::EXIT_SUCCESS)
+}
+
+/// Ends the current session, removing its toolchains unless `cfg.keep_toolchains` is set.
+pub fn reset(cfg: &Cfg, mr_cfg: &multirust::Cfg) -> Result<i32> {
+    let path = session_path();
+    if !path.exists() {
+        try!(cli::display_error("no bisect session in progress"));
+        return Ok(libc::EXIT_FAILURE);
+    }
+    // A session that can't be loaded can still be ended; `rust-bisect gc` gets the toolchains.
+    if let Ok(session) = Session::load(&path) {
+        let cfg = Cfg {
+            host: session.host.clone(),
+            ..cfg.clone()
+        };
+        try!(discard_toolchains(&cfg, mr_cfg, &session));
+    }
+    try!(fs::remove_file(&path));
+    Ok(libc::EXIT_SUCCESS)
+}
+
+/// Removes every toolchain in the bisect store, such as ones left behind by interrupted runs.
+pub fn gc(mr_cfg: &multirust::Cfg) -> Result<i32> {
+    let names = try!(mr_cfg.list_toolchains());
+    if names.is_empty() {
+        println!("no bisect toolchains to remove");
+    }
+    for name in names {
+        try!(try!(mr_cfg.get_toolchain(&name, false)).remove());
+    }
     Ok(libc::EXIT_SUCCESS)
 }
+
+/// How a check made before a session starts went.
+enum Check<T> {
+    Passed(T),
+    /// The problem has been explained.
+    Failed,
+    /// The user stopped in interactive mode.
+    Quit,
+}
+
+impl<T> Check<T> {
+    /// The exit code for a check that didn't pass. Stopping isn't a failure, but nothing is saved
+    /// to continue from, since there is no session yet.
+    fn exit_code(self, cfg: &Cfg) -> i32 {
+        match self {
+            Check::Quit => {
+                progress!(cfg.format,
+                          "stopping; nothing was saved, since the session only starts once the \
+                           endpoints are settled");
+                libc::EXIT_SUCCESS
+            }
+            _ => libc::EXIT_FAILURE,
+        }
+    }
+}
+
+/// Looks for a good nightly before `bad`, stepping back one week, then two weeks further, then
+/// four, and so on. Passes with the good nightly along with the closest bad one seen.
+fn find_good(cfg: &Cfg,
+             mr_cfg: &multirust::Cfg,
+             bad: Nightly)
+             -> Result<Check<(Nightly, Nightly)>> {
+    if !cfg.no_verify {
+        progress!(cfg.format, "checking that {} is bad", bad);
+        match try!(judge_version(cfg, mr_cfg, &Version::Nightly(bad))).map(|step| step.verdict) {
+            Some(Verdict::Bad) => {}
+            Some(_) => {
+                try!(cli::display_error(format!("{} isn't bad", bad)));
+                progress!(cfg.format, "pass `--no-verify` to bisect anyway");
+                return Ok(Check::Failed);
+            }
+            None => return Ok(Check::Quit),
+        }
+    }
+
+    progress!(cfg.format, "looking for a good nightly before {}", bad);
+    // Each step goes back twice as far as the last, counted from the original `bad`, which moves
+    // as bad nightlies are found.
+    let start = bad;
+    let mut bad = bad;
+    let mut weeks = 0;
+    let mut step = 1;
+    while weeks + step <= GOOD_SEARCH_MAX_WEEKS {
+        weeks += step;
+        // A missing nightly can't be installed, so it is skipped like any other untestable one.
+        let nightly = Nightly::from(start.date - Duration::weeks(weeks));
+        let version = Version::Nightly(nightly);
+        match try!(judge_version(cfg, mr_cfg, &version)).map(|step| step.verdict) {
+            Some(Verdict::Good) => {
+                progress!(cfg.format, "found good nightly {}", nightly);
+                return Ok(Check::Passed((nightly, bad)));
+            }
+            Some(Verdict::Bad) => {
+                try!(discard_toolchain(cfg, mr_cfg, &Version::Nightly(bad)));
+                bad = nightly;
+            }
+            Some(Verdict::Skip) => try!(discard_toolchain(cfg, mr_cfg, &version)),
+            None => return Ok(Check::Quit),
+        }
+        step *= 2;
+    }
+    try!(cli::display_error(format!("no good nightly found in the {} weeks before {}",
+                                    weeks,
+                                    start)));
+    Ok(Check::Failed)
+}
+
+/// Checks that `good` is good and `bad` is bad, since otherwise bisecting between them finds
+/// nothing meaningful. Fails after explaining the problem if they aren't.
+fn verify_endpoints(cfg: &Cfg,
+                    mr_cfg: &multirust::Cfg,
+                    good: &Version,
+                    bad: &Version)
+                    -> Result<Check<()>> {
+    progress!(cfg.format, "checking that {} is good and {} is bad", good, bad);
+    let good_verdict = match try!(judge_version(cfg, mr_cfg, good)) {
+        Some(step) => step.verdict,
+        None => return Ok(Check::Quit),
+    };
+    let bad_verdict = match try!(judge_version(cfg, mr_cfg, bad)) {
+        Some(step) => step.verdict,
+        None => return Ok(Check::Quit),
+    };
+
+    let (earlier, later) = if cfg.find_fix {
+        ("a later", "an earlier")
+    } else {
+        ("an earlier", "a later")
+    };
+    let problem = match (good_verdict, bad_verdict) {
+        (Verdict::Good, Verdict::Bad) => return Ok(Check::Passed(())),
+        (Verdict::Bad, Verdict::Bad) => {
+            format!("{} and {} are both bad; try {} `--good`", good, bad, earlier)
+        }
+        (Verdict::Good, Verdict::Good) => {
+            format!("{} and {} are both good; try {} `--bad`", good, bad, later)
+        }
+        (Verdict::Bad, Verdict::Good) if cfg.find_fix => {
+            format!("{} is bad but {} is good, so it looks like the behavior was broken rather \
+                     than fixed; try without `--find-fix`",
+                    good,
+                    bad)
+        }
+        (Verdict::Bad, Verdict::Good) => {
+            format!("{} is bad but {} is good, so it looks like the behavior was fixed rather \
+                     than broken; try `--find-fix`, with `--good {}` and `--bad {}`",
+                    good,
+                    bad,
+                    bad,
+                    good)
+        }
+        (Verdict::Skip, _) => format!("could not test with {}", good),
+        (_, Verdict::Skip) => format!("could not test with {}", bad),
+    };
+    try!(cli::display_error(problem));
+    progress!(cfg.format, "pass `--no-verify` to bisect anyway");
+    Ok(Check::Failed)
+}
+
+/// The endpoints to bisect between. The newer one defaults to the latest nightly: `bad`, or
+/// `good` when looking for a fix. If that leaves a release alongside it, the release is resolved
+/// to a nightly too.
+fn endpoints(cfg: &Cfg, mr_cfg: &multirust::Cfg) -> Result<(Option<Version>, Version)> {
+    let latest = || nightlies::latest(mr_cfg).map(Version::Nightly);
+    let (good, bad) = if cfg.find_fix {
+        let bad = try!(cfg.bad.clone().ok_or("`bad` has to be given when looking for a fix"));
+        let good = match cfg.good {
+            Some(ref good) => good.clone(),
+            None => try!(latest()),
+        };
+        (Some(good), bad)
+    } else {
+        let bad = match cfg.bad {
+            Some(ref bad) => bad.clone(),
+            None => try!(latest()),
+        };
+        (cfg.good.clone(), bad)
+    };
+    let (good, bad) = resolve_releases(cfg.format, good, Some(bad));
+    let bad = bad.expect("`bad` was given");
+    // The command line can only check the order when both endpoints are given, so check it again
+    // now that the latest nightly has been filled in.
+    if let Some(ref good) = good {
+        try!(check_order(good, &bad, cfg.find_fix));
+    }
+    Ok((good, bad))
+}
+
+/// Checks that `bad` comes after `good`, or before it when looking for a fix, since the bad
+/// endpoint comes first then.
+fn check_order(good: &Version, bad: &Version, find_fix: bool) -> Result<()> {
+    let (older, newer) = if find_fix {
+        (bad, good)
+    } else {
+        (good, bad)
+    };
+    let in_order = match (older, newer) {
+        (&Version::Nightly(ref older), &Version::Nightly(ref newer)) => older.date < newer.date,
+        (&Version::Release(ref older), &Version::Release(ref newer)) => older < newer,
+        _ => unreachable!("mixed endpoints were resolved to nightlies"),
+    };
+    if in_order {
+        Ok(())
+    } else if find_fix {
+        Err(Error::from(format!("`good` ({}) must be after `bad` ({}) when looking for a fix",
+                                good,
+                                bad)))
+    } else {
+        Err(Error::from(format!("`bad` ({}) must be after `good` ({})", bad, good)))
+    }
+}
+
+/// Resolves a release given alongside a nightly to the nightly it was branched from, since the
+/// two can't be bisected across together.
+fn resolve_releases(format: Format,
+                    good: Option<Version>,
+                    bad: Option<Version>)
+                    -> (Option<Version>, Option<Version>) {
+    match (good, bad) {
+        (Some(Version::Release(good)), Some(Version::Nightly(bad))) => {
+            (Some(branch_nightly(format, "good", &good)), Some(Version::Nightly(bad)))
+        }
+        (Some(Version::Nightly(good)), Some(Version::Release(bad))) => {
+            (Some(Version::Nightly(good)), Some(branch_nightly(format, "bad", &bad)))
+        }
+        (good, bad) => (good, bad),
+    }
+}
+
+/// Resolves `release`, given for the `which` endpoint alongside a nightly, to the nightly it was
+/// branched from.
+fn branch_nightly(format: Format, which: &str, release: &Release) -> Version {
+    let nightly = Nightly::from(release.branch_date());
+    progress!(format,
+              "using {} for `{}`, the nightly {} was branched from",
+              nightly,
+              which,
+              release);
+    Version::Nightly(nightly)
+}
+
+/// Saves a new session bisecting between `good` and `bad`. Returns `None` if there is nothing to
+/// bisect across.
+fn start_session(cfg: &Cfg,
+                 mr_cfg: &multirust::Cfg,
+                 good: Version,
+                 bad: Version)
+                 -> Result<Option<Session>> {
+    let (older, newer) = if cfg.find_fix {
+        (&bad, &good)
+    } else {
+        (&good, &bad)
+    };
+    let candidates = match (older, newer) {
+        (&Version::Nightly(from), &Version::Nightly(to)) => {
+            progress!(cfg.format, "finding available nightlies between {} and {}", from, to);
+            let nightlies = try!(nightlies::list_available(mr_cfg, from.date, to.date));
+            if nightlies.is_empty() {
+                try!(cli::display_error(format!("no nightlies found between {} and {}",
+                                                from,
+                                                to)));
+                return Ok(None);
+            }
+            nightlies.into_iter().map(Version::Nightly).collect()
+        }
+        (&Version::Release(ref from), &Version::Release(ref to)) => {
+            release::list_releases(from, to).into_iter().map(Version::Release).collect()
+        }
+        _ => return Err(Error::from("`good` and `bad` must both be nightlies or both be releases")),
+    };
+
+    let session = Session::new(cfg, good, bad, candidates);
+    try!(session.save(&session_path()));
+    Ok(Some(session))
+}
+
+/// Runs the command for each remaining step of `session`, saving it after each one, and reports
+/// the result.
+fn bisect(cfg: &Cfg, mr_cfg: &multirust::Cfg, mut session: Session) -> Result<i32> {
+    let path = session_path();
+    let remaining = session.remaining();
+    progress!(cfg.format,
+              "bisecting across {} {} (about {} steps)",
+              remaining,
+              kind_names(&session.bad).1,
+              estimate_steps(remaining));
+
+    if cfg.jobs > 1 {
+        try!(bisect_in_batches(cfg, &mut session));
+    }
+    while let Some(version) = session.next().cloned() {
+        let prefetching = if cfg.no_prefetch {
+            Vec::new()
+        } else {
+            prefetch(cfg, session.upcoming(&version))
+        };
+        let step = try!(judge_version(cfg, mr_cfg, &version));
+        // The next step may install one of the same toolchains, so let prefetching finish first.
+        for handle in prefetching {
+            let _ = handle.join();
+        }
+        let step = match step {
+            Some(step) => step,
+            None => {
+                progress!(cfg.format, "stopping; continue with `rust-bisect run --interactive`");
+                return Ok(libc::EXIT_SUCCESS);
+            }
+        };
+        try!(session.record(step));
+        try!(session.save(&path));
+    }
+    let (before, after) = session.boundary();
+    let hashes = (commit_hash(cfg, mr_cfg, before), commit_hash(cfg, mr_cfg, after));
+    if cfg.format == Format::Text {
+        report(&session);
+        try!(report_commits(before, after, &hashes));
+    }
+    if cfg.format == Format::Json || cfg.output.is_some() {
+        let results = Results::new(&session,
+                                   hashes.0.as_ref().ok().cloned(),
+                                   hashes.1.as_ref().ok().cloned());
+        if let Some(ref output) = cfg.output {
+            try!(results.save(output));
+        }
+        // Only the refined result is reported when refining, so that stdout has a single JSON
+        // document.
+        let refining = cfg.refine && release_bounds(&session).is_some();
+        if cfg.format == Format::Json && !refining {
+            println!("{}", results.to_pretty_json());
+        }
+    }
+    try!(discard_toolchains(cfg, mr_cfg, &session));
+
+    let (before, after) = match release_bounds(&session) {
+        Some((before, after)) => (before.clone(), after.clone()),
+        None => return Ok(libc::EXIT_SUCCESS),
+    };
+    progress!(cfg.format,
+              "{} branched from nightly around {}, and {} around {}",
+              before,
+              before.branch_date(),
+              after,
+              after.branch_date());
+
+    // Widen the window by a week on each side, in case the release schedule slipped.
+    let from = Version::Nightly(Nightly::from(before.branch_date() - Duration::weeks(1)));
+    let to = Version::Nightly(Nightly::from(after.branch_date() + Duration::weeks(1)));
+    let (good, bad) = if cfg.find_fix {
+        (to, from)
+    } else {
+        (from, to)
+    };
+    if !cfg.refine {
+        progress!(cfg.format,
+                  "rerun with `--refine`, or with `--good {} --bad {}`, to find the nightly",
+                  good,
+                  bad);
+        return Ok(libc::EXIT_SUCCESS);
+    }
+    if !cfg.no_verify {
+        match try!(verify_endpoints(cfg, mr_cfg, &good, &bad)) {
+            Check::Passed(()) => {}
+            check => return Ok(check.exit_code(cfg)),
+        }
+    }
+    match try!(start_session(cfg, mr_cfg, good, bad)) {
+        Some(session) => bisect(cfg, mr_cfg, session),
+        None => Ok(libc::EXIT_FAILURE),
+    }
+}
+
+/// Runs the remaining steps of `session`, testing up to `cfg.jobs` versions at once.
+fn bisect_in_batches(cfg: &Cfg, session: &mut Session) -> Result<()> {
+    let path = session_path();
+    loop {
+        let batch = session.next_batch(cfg.jobs);
+        if batch.is_empty() {
+            return Ok(());
+        }
+        progress!(cfg.format,
+                  "testing with {}",
+                  batch.iter().map(|version| version.to_string()).collect::<Vec<_>>().join(", "));
+
+        for step in try!(test_versions(cfg, &batch)) {
+            let verdict = step.verdict;
+            // A flaky command can give verdicts that contradict each other, in which case the
+            // first flip wins.
+            if let Err(e) = session.record(step) {
+                try!(cli::display_error(format!("ignoring {} verdict: {}", verdict, e)));
+            }
+        }
+        try!(session.save(&path));
+    }
+}
+
+/// Runs `f` on each of `versions` in a thread of its own.
+///
+/// `multirust::Cfg` can't be shared between threads, so each thread loads its own, using the
+/// notify handler from `notify_handler`. `f` is passed `None` if it can't be loaded.
+fn spawn_each<T, F>(cfg: &Cfg,
+                    versions: Vec<Version>,
+                    notify_handler: fn(Format) -> multirust::SharedNotifyHandler,
+                    f: F)
+                    -> Vec<thread::JoinHandle<T>>
+    where T: Send + 'static,
+          F: Fn(&Cfg, Option<&multirust::Cfg>, Version) -> T + Send + Sync + 'static
+{
+    let f = Arc::new(f);
+    versions.into_iter()
+            .map(|version| {
+                let (cfg, f) = (cfg.clone(), f.clone());
+                thread::spawn(move || {
+                    let mr_cfg = load_mr_cfg(notify_handler(cfg.format)).ok();
+                    f(&cfg, mr_cfg.as_ref(), version)
+                })
+            })
+            .collect()
+}
+
+/// Tests each of `versions` in a thread of its own.
+fn test_versions(cfg: &Cfg, versions: &[Version]) -> Result<Vec<Step>> {
+    let handles = spawn_each(cfg,
+                             versions.to_vec(),
+                             cli::notify_handler,
+                             |cfg, mr_cfg, version| {
+                                 // A `Box<Error>` can't be returned through `join`, so the
+                                 // message stands in for it until `try!` below makes it an
+                                 // error again.
+                                 match mr_cfg {
+                                     Some(mr_cfg) => {
+                                         test_version(cfg, mr_cfg, &version)
+                                             .map_err(|e| e.to_string())
+                                     }
+                                     None => Ok(Step::without_command(version, Verdict::Skip)),
+                                 }
+                             });
+    let mut steps = Vec::with_capacity(versions.len());
+    for (version, handle) in versions.iter().zip(handles) {
+        match handle.join() {
+            Ok(step) => steps.push(try!(step)),
+            Err(_) => steps.push(Step::without_command(version.clone(), Verdict::Skip)),
+        }
+    }
+    Ok(steps)
+}
+
+fn quiet_notify_handler(_: Format) -> multirust::SharedNotifyHandler {
+    multirust::SharedNotifyHandler::none()
+}
+
+/// Installs each of `versions` in the background, so that they are ready if they are tested next.
+fn prefetch(cfg: &Cfg, versions: Vec<Version>) -> Vec<thread::JoinHandle<()>> {
+    spawn_each(cfg,
+               versions,
+               quiet_notify_handler,
+               |cfg, mr_cfg, version| {
+                   // Any failure will come up again if the version is actually tested.
+                   if let Some(mr_cfg) = mr_cfg {
+                       let _ = version.install(cfg, mr_cfg);
+                   }
+               })
+}
+
+/// Gets the next version to test in a session driven by hand ready, or reports the result if the
+/// bisection is over.
+fn next_step(session: &Session, mr_cfg: &multirust::Cfg) -> Result<i32> {
+    let version = match session.next() {
+        Some(version) => version,
+        None => {
+            report(session);
+            return Ok(libc::EXIT_SUCCESS);
+        }
+    };
+
+    let remaining = session.remaining();
+    println!("{} left to test (about {} steps)",
+             remaining,
+             estimate_steps(remaining));
+    let cfg = Cfg {
+        components: session.components.clone(),
+        targets: session.targets.clone(),
+        host: session.host.clone(),
+        ..Cfg::default()
+    };
+    let toolchain = match version.install(&cfg, mr_cfg) {
+        Ok(toolchain) => toolchain,
+        Err(e) => {
+            try!(cli::display_error(format!("could not install {}: {}", version, e)));
+            println!("mark it as untestable with `rust-bisect skip`");
+            return Ok(libc::EXIT_FAILURE);
+        }
+    };
+    println!("test with {}, which is installed in {}",
+             version,
+             toolchain.prefix().path().display());
+    println!("then mark it with `rust-bisect good`, `rust-bisect bad` or `rust-bisect skip`, or \
+              continue automatically with `rust-bisect run <COMMAND>`");
+    Ok(libc::EXIT_SUCCESS)
+}
+
+/// Prints the first failing version found by a finished session, or the first fixed one when
+/// looking for a fix.
+fn report(session: &Session) {
+    let (kind, kinds) = kind_names(&session.bad);
+    let change = if session.find_fix {
+        "fixed"
+    } else {
+        "failing"
+    };
+    let found = session.bounds();
+    // The search only covers versions before the newer endpoint, which is assumed to have changed.
+    let first = session.candidates.get(found.end).unwrap_or(session.newer());
+    if found.start == found.end {
+        println!("{} is the first {} {}", first, change, kind);
+    } else {
+        println!("the first {} {} is between {} and {}; {} {} in between could not be tested",
+                 change,
+                 kind,
+                 session.candidates[found.start],
+                 first,
+                 found.len(),
+                 kinds);
+    }
+    if let Some(confidence) = session.confidence() {
+        println!("this is {:.1}% likely, allowing for wrong verdicts from a flaky command",
+                 confidence * 100.0);
+    }
+}
+
+/// Prints the commits that `before` and `after`, the versions either side of the change, were
+/// built from, and where to see the changes between them.
+fn report_commits(before: &Version,
+                  after: &Version,
+                  hashes: &(Result<String>, Result<String>))
+                  -> Result<()> {
+    let (before_hash, after_hash) = match *hashes {
+        (Ok(ref before_hash), Ok(ref after_hash)) => (before_hash, after_hash),
+        (Err(ref e), _) | (_, Err(ref e)) => {
+            return cli::display_error(format!("could not find the commits between {} and {}: {}",
+                                              before,
+                                              after,
+                                              e));
+        }
+    };
+    println!("{} was built from {}", before, before_hash);
+    println!("{} was built from {}", after, after_hash);
+    println!("see the changes in between with `git log {}..{}` in a Rust checkout, or at",
+             before_hash,
+             after_hash);
+    println!("{}/compare/{}...{}", RUST_REPO_URL, before_hash, after_hash);
+    Ok(())
+}
+
+/// For a finished bisection across releases, returns the last release before the change and the
+/// first one after it.
+fn release_bounds(session: &Session) -> Option<(&Release, &Release)> {
+    let found = session.bounds();
+    if found.start != found.end || found.end == 0 {
+        return None;
+    }
+    let after = session.candidates.get(found.end).unwrap_or(session.newer());
+    match (&session.candidates[found.end - 1], after) {
+        (&Version::Release(ref before), &Version::Release(ref after)) => Some((before, after)),
+        _ => None,
+    }
+}
+
+/// Removes the toolchain for `version` from the bisect store, along with its target dir, unless
+/// `cfg.keep_toolchains` is set.
+fn discard_toolchain(cfg: &Cfg, mr_cfg: &multirust::Cfg, version: &Version) -> Result<()> {
+    if !cfg.keep_toolchains {
+        try!(try!(mr_cfg.get_toolchain(&version.toolchain_name(cfg), false)).remove_if_exists());
+        let target_dir = target_dir(cfg, version);
+        if target_dir.exists() {
+            try!(fs::remove_dir_all(target_dir));
+        }
+    }
+    Ok(())
+}
+
+/// Removes the toolchains for all of the versions in `session`, both endpoints included, unless
+/// `cfg.keep_toolchains` is set.
+fn discard_toolchains(cfg: &Cfg, mr_cfg: &multirust::Cfg, session: &Session) -> Result<()> {
+    let endpoints = [session.older(), session.newer()];
+    for version in session.candidates.iter().chain(endpoints.iter().cloned()) {
+        try!(discard_toolchain(cfg, mr_cfg, version));
+    }
+    Ok(())
+}
+
+fn kind_names(version: &Version) -> (&'static str, &'static str) {
+    match *version {
+        Version::Nightly(_) => ("nightly", "nightlies"),
+        Version::Release(_) => ("release", "releases"),
+    }
+}
+
+fn estimate_steps(candidates: usize) -> u32 {
+    candidates.next_power_of_two().trailing_zeros()
+}
+
+/// Decides on a verdict for `version`, by asking the user in interactive mode or else by running
+/// the command. Returns `None` if the user wants to stop.
+fn judge_version(cfg: &Cfg, mr_cfg: &multirust::Cfg, version: &Version) -> Result<Option<Step>> {
+    if cfg.interactive {
+        ask_version(cfg, mr_cfg, version)
+    } else {
+        test_version(cfg, mr_cfg, version).map(Some)
+    }
+}
+
+/// Runs the command with `version` to decide on a verdict.
+///
+/// The version is untestable if it can't be installed, if the command can't be run, or if the
+/// command exits with `SKIP_EXIT_CODE`. Failing to save or show the command's output is no fault
+/// of the version's, so that is an error instead.
+fn test_version(cfg: &Cfg, mr_cfg: &multirust::Cfg, version: &Version) -> Result<Step> {
+    progress!(cfg.format, "testing with {}", version);
+
+    let mut step = Step::without_command(version.clone(), Verdict::Skip);
+    let mut verdicts = Vec::new();
+    for run in 0..cfg.runs {
+        if cfg.runs > 1 {
+            progress!(cfg.format, "run {} of {}", run + 1, cfg.runs);
+        }
+        match run_with_version(cfg, mr_cfg, version) {
+            Ok(outcome) => {
+                try!(show_outcome(cfg, mr_cfg, version, run, &outcome));
+                verdicts.push(judge_outcome(cfg, version, &outcome));
+                // The step keeps the last run's exit code, and how long all of them took.
+                step.exit_code = outcome.output.status.code();
+                step.duration = Some(step.duration.unwrap_or(time::Duration::new(0, 0)) +
+                                     outcome.duration);
+            }
+            Err(e) => {
+                let _ = cli::display_error(format!("could not test with {}: {}", version, e));
+                progress!(cfg.format, "skipping {}", version);
+                return Ok(Step::without_command(version.clone(), Verdict::Skip));
+            }
+        }
+    }
+
+    step.verdict = cfg.vote.tally(&verdicts);
+    if cfg.runs > 1 {
+        let bad = verdicts.iter().filter(|&&verdict| verdict == Verdict::Bad).count();
+        progress!(cfg.format,
+                  "{} of {} runs with {} were bad, so it is {} by `{}` vote",
+                  bad,
+                  cfg.runs,
+                  version,
+                  step.verdict,
+                  cfg.vote);
+    }
+    Ok(step)
+}
+
+/// Decides on a verdict for `version` from how the command went.
+fn judge_outcome(cfg: &Cfg, version: &Version, outcome: &Outcome) -> Verdict {
+    if outcome.timed_out {
+        let verdict = cfg.timeout.expect("timed out without a timeout").verdict;
+        progress!(cfg.format, "command timed out with {}, so it is {}", version, verdict);
+        return verdict;
+    }
+    let output = &outcome.output;
+    let status = output.status;
+
+    if status.code() == Some(SKIP_EXIT_CODE) {
+        progress!(cfg.format, "command skipped {}", version);
+        return Verdict::Skip;
+    }
+
+    // Output patterns take precedence over the exit code, with `bad_pattern` checked first.
+    let patterns = [(&cfg.bad_pattern, Verdict::Bad), (&cfg.good_pattern, Verdict::Good)];
+    for &(pattern, verdict) in &patterns {
+        if let Some(ref pattern) = *pattern {
+            if output_matches(output, pattern) {
+                progress!(cfg.format,
+                          "command output matched `{}`, so {} is {}",
+                          pattern,
+                          version,
+                          verdict);
+                return verdict;
+            }
+        }
+    }
+
+    progress!(cfg.format,
+              "command {} with {}",
+              if status.success() {
+                  "succeeded"
+              } else {
+                  "failed"
+              },
+              version);
+    let verdict = if cfg.regress.is_bad(outcome) {
+        Verdict::Bad
+    } else {
+        Verdict::Good
+    };
+    if cfg.regress != Regress::Error {
+        progress!(cfg.format, "{} is {} when looking for `{}`", version, verdict, cfg.regress);
+    }
+    verdict
+}
+
+/// Installs `version`, runs the command if there is one, and asks the user for a verdict. Returns
+/// `None` if the user wants to stop.
+fn ask_version(cfg: &Cfg, mr_cfg: &multirust::Cfg, version: &Version) -> Result<Option<Step>> {
+    progress!(cfg.format, "testing with {}", version);
+
+    let toolchain = match version.install(cfg, mr_cfg) {
+        Ok(toolchain) => toolchain,
+        Err(e) => {
+            try!(cli::display_error(format!("could not install {}: {}", version, e)));
+            progress!(cfg.format, "skipping {}", version);
+            return Ok(Some(Step::without_command(version.clone(), Verdict::Skip)));
+        }
+    };
+    let mut outcome = None;
+    if cfg.has_command() {
+        match run_with_version(cfg, mr_cfg, version) {
+            Ok(o) => {
+                try!(show_outcome(cfg, mr_cfg, version, 0, &o));
+                if o.timed_out {
+                    progress!(cfg.format, "with {}, command timed out", version);
+                } else {
+                    progress!(cfg.format,
+                              "with {}, command finished with {}",
+                              version,
+                              o.output.status);
+                    outcome = Some(o);
+                }
+            }
+            Err(e) => try!(cli::display_error(format!("could not run command with {}: {}",
+                                                      version,
+                                                      e))),
+        }
+    }
+    progress!(cfg.format,
+              "{} is installed in {}",
+              version,
+              toolchain.prefix().path().display());
+    Ok(try!(cli::ask_verdict(cfg, version)).map(|verdict| {
+        Step {
+            version: version.clone(),
+            verdict: verdict,
+            exit_code: outcome.as_ref().and_then(|o| o.output.status.code()),
+            duration: outcome.as_ref().map(|o| o.duration),
+        }
+    }))
+}
+
+/// What came of running the command for one step.
+struct Outcome {
+    /// The command's exit status, and its output if it was captured.
+    output: Output,
+    /// Whether the command was killed for running past the timeout.
+    timed_out: bool,
+    /// How long the command ran for.
+    duration: time::Duration,
+    /// Whether the status is that of a program compiled from `cfg.rust_file`, rather than of
+    /// rustc or the command, so that it can't be an internal compiler error.
+    ran_binary: bool,
+}
+
+/// Runs the command with `version`, or compiles and maybe runs `cfg.rust_file`. Its output is
+/// only captured if it is needed to decide on a verdict, to be saved to `cfg.log_dir` or to go to
+/// stderr with `Format::Json`.
+fn run_with_version(cfg: &Cfg, mr_cfg: &multirust::Cfg, version: &Version) -> Result<Outcome> {
+    let toolchain = try!(version.install(cfg, mr_cfg));
+    let extra_env = try!(extra_env(cfg, version));
+    Ok(match cfg.rust_file {
+        Some(ref path) => try!(run_rust_file(cfg, mr_cfg, &toolchain, path, &extra_env)),
+        None => {
+            let cmd = try!(cfg.cmd.as_ref().ok_or("no command to run"));
+            let mut cmd = try!(toolchain.create_command(cmd));
+            cmd.args(&cfg.args);
+            try!(run_command(cfg, cmd, &extra_env))
+        }
+    })
+}
+
+/// What rust-bisect sets in the command's environment when running it with `version`.
+fn extra_env(cfg: &Cfg, version: &Version) -> Result<Vec<(String, OsString)>> {
+    let mut extra_env = Vec::new();
+    if cfg.isolate_target_dir {
+        // Absolute, in case the command changes directory before running cargo.
+        let target_dir = try!(env::current_dir()).join(target_dir(cfg, version));
+        extra_env.push((String::from("CARGO_TARGET_DIR"), target_dir.into_os_string()));
+    }
+    for &(ref name, ref value) in &cfg.env {
+        extra_env.push((name.clone(), OsString::from(value)));
+    }
+    Ok(extra_env)
+}
+
+/// Saves the output from `run` of the command with `version` to `cfg.log_dir`, and echoes it
+/// unless `cfg.quiet_steps` is set and the command succeeded.
+fn show_outcome(cfg: &Cfg,
+                mr_cfg: &multirust::Cfg,
+                version: &Version,
+                run: usize,
+                outcome: &Outcome)
+                -> Result<()> {
+    if let Some(ref dir) = cfg.log_dir {
+        let toolchain = try!(mr_cfg.get_toolchain(&version.toolchain_name(cfg), false));
+        let extra_env = try!(extra_env(cfg, version));
+        try!(logs::save(dir, cfg, &toolchain, version, run, &extra_env, outcome));
+    }
+    if !cfg.quiet_steps || outcome.timed_out || !outcome.output.status.success() {
+        // With `Format::Json`, stdout is kept for the report.
+        if cfg.format == Format::Json {
+            try!(io::stderr().write_all(&outcome.output.stdout));
+        } else {
+            try!(io::stdout().write_all(&outcome.output.stdout));
+        }
+        try!(io::stderr().write_all(&outcome.output.stderr));
+    }
+    Ok(())
+}
+
+/// Compiles the single-file program at `path` with the toolchain's rustc into a temporary
+/// directory, and then runs it if `cfg.run_binary` is set and it compiled. The outcome is the
+/// compiler's if it didn't get as far as running the program, and otherwise the program's, with
+/// the output of both.
+fn run_rust_file(cfg: &Cfg,
+                 mr_cfg: &multirust::Cfg,
+                 toolchain: &multirust::Toolchain,
+                 path: &Path,
+                 extra_env: &[(String, OsString)])
+                 -> Result<Outcome> {
+    let dir = try!(mr_cfg.temp_cfg.new_directory());
+    let binary = dir.join("repro");
+    let mut rustc = try!(toolchain.create_command("rustc"));
+    rustc.arg(path).arg("-o").arg(&binary);
+    let compiled = try!(run_command(cfg, rustc, extra_env));
+    if !cfg.run_binary || compiled.timed_out || !compiled.output.status.success() {
+        return Ok(compiled);
+    }
+
+    // Run through the toolchain, so that the program can find its libraries if it links to them
+    // dynamically.
+    let ran = try!(run_command(cfg, try!(toolchain.create_command(&binary)), extra_env));
+    let mut stdout = compiled.output.stdout;
+    stdout.extend(ran.output.stdout);
+    let mut stderr = compiled.output.stderr;
+    stderr.extend(ran.output.stderr);
+    Ok(Outcome {
+        output: Output {
+            status: ran.output.status,
+            stdout: stdout,
+            stderr: stderr,
+        },
+        timed_out: ran.timed_out,
+        duration: compiled.duration + ran.duration,
+        ran_binary: true,
+    })
+}
+
+/// Runs `cmd` with `extra_env` added to its environment, killing it after `cfg.timeout`.
+fn run_command(cfg: &Cfg,
+               mut cmd: process::Command,
+               extra_env: &[(String, OsString)])
+               -> Result<Outcome> {
+    for &(ref name, ref value) in extra_env {
+        cmd.env(name, value);
+    }
+    if cfg.timeout.is_some() {
+        new_process_group(&mut cmd);
+    }
+    if cfg.captures_output() {
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    }
+
+    let started = time::Instant::now();
+    let child = try!(cmd.spawn());
+    let timed_out = Arc::new(AtomicBool::new(false));
+    let output = match cfg.timeout {
+        Some(timeout) => wait_with_timeout(child, timeout.secs, timed_out.clone()),
+        None => child.wait_with_output(),
+    };
+    Ok(Outcome {
+        output: try!(output),
+        timed_out: timed_out.load(Ordering::SeqCst),
+        duration: started.elapsed(),
+        ran_binary: false,
+    })
+}
+
+/// Set once rust-bisect is interrupted, to pass it on to the commands in process groups of their
+/// own, which the terminal's Ctrl-C doesn't reach.
+#[cfg(unix)]
+static INTERRUPTED: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// How many commands are running in process groups of their own.
+#[cfg(unix)]
+static GROUPS_RUNNING: AtomicUsize = ATOMIC_USIZE_INIT;
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+    // With no command to pass it on to, stop as if the signal hadn't been caught.
+    if GROUPS_RUNNING.load(Ordering::SeqCst) == 0 {
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+            libc::raise(libc::SIGINT);
+        }
+    }
+}
+
+/// Gives the command its own process group, so that anything it starts can be killed along
+/// with it. Only the foreground process group can read from the terminal, so it gets no stdin.
+#[cfg(unix)]
+fn new_process_group(cmd: &mut process::Command) {
+    cmd.stdin(Stdio::null());
+    cmd.before_exec(|| {
+        if unsafe { libc::setpgid(0, 0) } == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn new_process_group(_: &mut process::Command) {}
+
+/// Waits for `child` and collects its output, but kills its process group if it is still running
+/// after `secs` seconds, and sets `timed_out`. If rust-bisect is interrupted meanwhile, the
+/// interrupt is passed on to the group, and rust-bisect exits once the command has.
+#[cfg(unix)]
+fn wait_with_timeout(child: Child, secs: u64, timed_out: Arc<AtomicBool>) -> io::Result<Output> {
+    static HANDLE_INTERRUPTS: Once = ONCE_INIT;
+    HANDLE_INTERRUPTS.call_once(|| {
+        let handler: extern "C" fn(libc::c_int) = on_interrupt;
+        unsafe {
+            libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+        }
+    });
+    GROUPS_RUNNING.fetch_add(1, Ordering::SeqCst);
+
+    let pgid = child.id() as libc::pid_t;
+    let deadline = time::Instant::now() + time::Duration::from_secs(secs);
+    // The channel is closed once the command exits, which stops the thread.
+    let (tx, rx) = mpsc::channel::<()>();
+    thread::spawn(move || {
+        while let Err(mpsc::RecvTimeoutError::Timeout) =
+                  rx.recv_timeout(time::Duration::from_millis(100)) {
+            if INTERRUPTED.load(Ordering::SeqCst) {
+                unsafe {
+                    libc::kill(-pgid, libc::SIGINT);
+                }
+                return;
+            }
+            if time::Instant::now() >= deadline {
+                timed_out.store(true, Ordering::SeqCst);
+                unsafe {
+                    libc::kill(-pgid, libc::SIGKILL);
+                }
+                return;
+            }
+        }
+    });
+    let output = child.wait_with_output();
+    drop(tx);
+
+    GROUPS_RUNNING.fetch_sub(1, Ordering::SeqCst);
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        process::exit(128 + libc::SIGINT);
+    }
+    output
+}
+
+/// Waits for `child` and collects its output, but kills it if it is still running after `secs`
+/// seconds, and sets `timed_out`. Without process groups, anything it started is left running.
+#[cfg(not(unix))]
+fn wait_with_timeout(mut child: Child,
+                     secs: u64,
+                     timed_out: Arc<AtomicBool>)
+                     -> io::Result<Output> {
+    use std::io::Read;
+
+    fn read_all<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<Vec<u8>> {
+        thread::spawn(move || {
+            let mut buf = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buf);
+            }
+            buf
+        })
+    }
+
+    // Read the pipes on their own threads, so that the child can't block on a full pipe while
+    // it is being polled.
+    let stdout = read_all(child.stdout.take());
+    let stderr = read_all(child.stderr.take());
+    let deadline = time::Instant::now() + time::Duration::from_secs(secs);
+    while try!(child.try_wait()).is_none() {
+        if time::Instant::now() >= deadline {
+            timed_out.store(true, Ordering::SeqCst);
+            try!(child.kill());
+            break;
+        }
+        thread::sleep(time::Duration::from_millis(100));
+    }
+    Ok(Output {
+        status: try!(child.wait()),
+        stdout: stdout.join().unwrap_or(Vec::new()),
+        stderr: stderr.join().unwrap_or(Vec::new()),
+    })
+}
+
+/// Gets the rust-lang/rust commit that `version`'s rustc was built from, from `rustc -vV`.
+fn commit_hash(cfg: &Cfg, mr_cfg: &multirust::Cfg, version: &Version) -> Result<String> {
+    const PREFIX: &'static str = "commit-hash: ";
+    let toolchain = try!(version.install(cfg, mr_cfg));
+    let output = try!(try!(toolchain.create_command("rustc")).arg("-vV").output());
+    let info = String::from_utf8_lossy(&output.stdout);
+    let line = try!(info.lines()
+                        .find(|line| line.starts_with(PREFIX))
+                        .ok_or_else(|| format!("no commit hash for {}", version)));
+    Ok(String::from(&line[PREFIX.len()..]))
+}
+
+/// Whether the command died with an internal compiler error.
+/// Whether rustc hit an internal compiler error. A compiled program that panics exits with the
+/// same code, so its outcome never counts.
+fn is_ice(outcome: &Outcome) -> bool {
+    let output = &outcome.output;
+    !outcome.ran_binary &&
+    (output.status.code() == Some(ICE_EXIT_CODE) ||
+     String::from_utf8_lossy(&output.stderr).contains("internal compiler error"))
+}
+
+fn output_matches(output: &Output, pattern: &Regex) -> bool {
+    pattern.is_match(&String::from_utf8_lossy(&output.stdout)) ||
+    pattern.is_match(&String::from_utf8_lossy(&output.stderr))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    #[test]
+    fn test_cfg_default() {
+        let cfg = Cfg::default();
+        assert_eq!((1, 1), (cfg.jobs, cfg.runs));
+    }
+
+    #[test]
+    fn test_tally() {
+        use Verdict::{Bad, Good, Skip};
+
+        assert_eq!(Bad, Vote::AnyFail.tally(&[Good, Bad, Good]));
+        assert_eq!(Good, Vote::AnyFail.tally(&[Good, Skip, Good]));
+        assert_eq!(Bad, Vote::Majority.tally(&[Bad, Good, Bad]));
+        assert_eq!(Good, Vote::Majority.tally(&[Good, Bad, Skip, Good]));
+        assert_eq!(Skip, Vote::Majority.tally(&[Good, Bad]));
+        assert_eq!(Bad, Vote::AllFail.tally(&[Bad, Skip, Bad]));
+        assert_eq!(Good, Vote::AllFail.tally(&[Bad, Good, Bad]));
+
+        // Skipped runs don't count, unless all of them were.
+        for &vote in &[Vote::AnyFail, Vote::Majority, Vote::AllFail] {
+            assert_eq!(Skip, vote.tally(&[Skip, Skip]));
+            assert_eq!(Skip, vote.tally(&[]));
+        }
+    }
+
+    fn version(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_check_order() {
+        let (older, newer) = (version("nightly-2016-03-01"), version("nightly-2016-03-15"));
+        assert!(check_order(&older, &newer, false).is_ok());
+        assert!(check_order(&newer, &older, false).is_err());
+        assert!(check_order(&newer, &newer, false).is_err());
+        assert!(check_order(&version("1.6.0"), &version("1.8.0"), false).is_ok());
+        assert!(check_order(&version("1.8.0"), &version("1.6.0"), false).is_err());
+    }
+
+    #[test]
+    fn test_check_order_find_fix() {
+        let (older, newer) = (version("nightly-2016-03-01"), version("nightly-2016-03-15"));
+        assert!(check_order(&newer, &older, true).is_ok());
+        assert!(check_order(&older, &newer, true).is_err());
+        // `--find-fix --bad` on the latest nightly leaves `good` defaulting to the same one.
+        assert!(check_order(&newer, &newer, true).is_err());
+        assert!(check_order(&version("1.8.0"), &version("1.6.0"), true).is_ok());
+        assert!(check_order(&version("1.6.0"), &version("1.8.0"), true).is_err());
+    }
+
+    #[test]
+    fn test_relative_date() {
+        let today = NaiveDate::from_ymd(2016, 3, 15);
+        let date = |s| relative_date(s, today);
+        assert_eq!(Some(today), date("today"));
+        assert_eq!(Some(NaiveDate::from_ymd(2016, 3, 14)), date("yesterday"));
+        assert_eq!(Some(NaiveDate::from_ymd(2016, 3, 8)), date("last-week"));
+        assert_eq!(Some(NaiveDate::from_ymd(2016, 2, 14)), date("-30d"));
+        assert_eq!(Some(NaiveDate::from_ymd(2016, 3, 1)), date("-2w"));
+        assert_eq!(Some(today), date("-0d"));
+
+        assert_eq!(None, date("-"));
+        assert_eq!(None, date("-d"));
+        assert_eq!(None, date("-5"));
+        assert_eq!(None, date("-5y"));
+        assert_eq!(None, date("--5d"));
+        assert_eq!(None, date("-+5d"));
+        assert_eq!(None, date("-1é"));
+        assert_eq!(None, date("-é1d"));
+        assert_eq!(None, date("-99999999999d"));
+        assert_eq!(None, date("2016-03-15"));
+    }
+}
@@ -0,0 +1,107 @@
+//! Saving the output of each step, so that it isn't lost once a bisection moves on.
+//!
+//! For each version tested, `DIR/<version>.stdout` and `DIR/<version>.stderr` hold the command's
+//! output, and `DIR/<version>.meta` holds what it was run with and how it went, one `key value`
+//! entry per line like a session. When the command is run more than once for each version, the
+//! files for each run are kept apart as `DIR/<version>-run-<N>.stdout` and so on. A version that
+//! is tested again, such as after resuming a session, gets `-attempt-<N>` added to its names, so
+//! the output behind earlier verdicts is never overwritten.
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use multirust;
+
+use {Cfg, Outcome, Result, Version};
+
+/// Saves the output of `run`, counting from zero, of the command with `version` in `dir`. `env` is
+/// what rust-bisect set in the command's environment.
+pub fn save(dir: &Path,
+            cfg: &Cfg,
+            toolchain: &multirust::Toolchain,
+            version: &Version,
+            run: usize,
+            env: &[(String, OsString)],
+            outcome: &Outcome)
+            -> Result<()> {
+    try!(fs::create_dir_all(dir));
+    let base = if cfg.runs > 1 {
+        format!("{}-run-{}", version, run + 1)
+    } else {
+        version.to_string()
+    };
+    let name = next_free_name(dir, &base);
+    let path = |ext: &str| dir.join(format!("{}.{}", name, ext));
+    try!(try!(File::create(path("stdout"))).write_all(&outcome.output.stdout));
+    try!(try!(File::create(path("stderr"))).write_all(&outcome.output.stderr));
+
+    let mut meta = try!(File::create(path("meta")));
+    if let Some(ref rust_file) = cfg.rust_file {
+        try!(writeln!(meta, "rust-file {}", rust_file.display()));
+        try!(writeln!(meta, "run-binary {}", cfg.run_binary));
+    } else {
+        let mut command = String::new();
+        for arg in cfg.cmd.iter().chain(&cfg.args) {
+            if !command.is_empty() {
+                command.push(' ');
+            }
+            command.push_str(&arg.to_string_lossy());
+        }
+        try!(writeln!(meta, "command {}", command));
+    }
+    // These are set by multirust-rs, on top of the environment rust-bisect was run in.
+    try!(writeln!(meta,
+                  "env MULTIRUST_TOOLCHAIN={}",
+                  toolchain.prefix().path().display()));
+    try!(writeln!(meta,
+                  "env MULTIRUST_HOME={}",
+                  toolchain.cfg().multirust_dir.display()));
+    for &(ref name, ref value) in env {
+        try!(writeln!(meta, "env {}={}", name, value.to_string_lossy()));
+    }
+    try!(writeln!(meta, "status {}", outcome.output.status));
+    try!(writeln!(meta, "timed-out {}", outcome.timed_out));
+    let duration = outcome.duration;
+    try!(writeln!(meta,
+                  "duration {}.{:03}s",
+                  duration.as_secs(),
+                  duration.subsec_nanos() / 1000000));
+    Ok(())
+}
+
+/// Returns `base` if no logs have been saved under it in `dir`, or else `base-attempt-<N>` for the
+/// first attempt `N` that is still free.
+fn next_free_name(dir: &Path, base: &str) -> String {
+    let mut name = base.to_string();
+    let mut attempt = 1;
+    while dir.join(format!("{}.stdout", name)).exists() {
+        attempt += 1;
+        name = format!("{}-attempt-{}", base, attempt);
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+
+    use super::next_free_name;
+
+    #[test]
+    fn test_next_free_name() {
+        let dir = env::temp_dir().join("rust-bisect-test-logs");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(next_free_name(&dir, "nightly-2016-03-01"), "nightly-2016-03-01");
+        File::create(dir.join("nightly-2016-03-01.stdout")).unwrap();
+        assert_eq!(next_free_name(&dir, "nightly-2016-03-01"),
+                   "nightly-2016-03-01-attempt-2");
+        File::create(dir.join("nightly-2016-03-01-attempt-2.stdout")).unwrap();
+        assert_eq!(next_free_name(&dir, "nightly-2016-03-01"),
+                   "nightly-2016-03-01-attempt-3");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,17 +1,30 @@
 extern crate libc;
-extern crate multirust;
 extern crate rust_install;
 
 extern crate rust_bisect;
 
 use std::process;
 
-use rust_bisect::{Cfg, Result, cli};
+use rust_bisect::{Cfg, Result, Verdict, Version, cli};
 
 fn main() {
     fn run() -> Result<i32> {
         let matches = cli::app().get_matches();
-        let cfg = match Cfg::from_matches(&matches) {
+        let (name, sub_matches) = matches.subcommand();
+        // Only bisecting takes settings, so the other subcommands don't read the config file. In
+        // particular, a broken one mustn't get in the way of `reset`.
+        let cfg = match name {
+            "" | "start" => Cfg::from_matches(sub_matches.unwrap_or(&matches)),
+            "run" => Cfg::from_run_matches(sub_matches.unwrap_or(&matches)),
+            "reset" => {
+                Ok(Cfg {
+                    keep_toolchains: sub_matches.map_or(false, |m| m.is_present("keep-toolchains")),
+                    ..Cfg::default()
+                })
+            }
+            _ => Ok(Cfg::default()),
+        };
+        let cfg = match cfg {
             Ok(cfg) => cfg,
             Err(ref e) => {
                 try!(cli::display_error(e));
@@ -19,9 +32,32 @@ fn main() {
                 return Ok(libc::EXIT_FAILURE);
             }
         };
-        let mr_cfg = try!(multirust::Cfg::from_env(cli::notify_handler()));
+        let mr_cfg = try!(rust_bisect::load_mr_cfg(cli::notify_handler(cfg.format)));
+
+        let res = match name {
+            "start" => rust_bisect::start(&cfg, &mr_cfg),
+            "good" | "bad" | "skip" => {
+                let verdict: Verdict = try!(name.parse());
+                let version = match sub_matches.and_then(|m| m.value_of("VERSION")) {
+                    Some(version) => Some(try!(version.parse::<Version>())),
+                    None => None,
+                };
+                rust_bisect::mark(verdict, version, &mr_cfg)
+            }
+            "status" => rust_bisect::status(),
+            "reset" => rust_bisect::reset(&cfg, &mr_cfg),
+            "gc" => rust_bisect::gc(&mr_cfg),
+            "run" => rust_bisect::resume(&cfg, &mr_cfg),
+            _ => rust_bisect::run(&cfg, &mr_cfg),
+        };
+        match res {
+            Ok(code) => Ok(code),
+            Err(ref e) => {
+                try!(cli::display_error(e));
 
-        rust_bisect::run(&cfg, &mr_cfg)
+                Ok(libc::EXIT_FAILURE)
+            }
+        }
     }
 
     process::exit(run().expect("something went wrong"));
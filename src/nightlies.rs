@@ -0,0 +1,260 @@
+//! Finding out which nightlies are available on the dist server.
+//!
+//! Probing is done concurrently, and the results are cached in the multirust home so that
+//! bisections over overlapping ranges don't probe the same dates again.
+
+use std::cmp;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use chrono::{self, Duration, NaiveDate};
+use hyper::client::Client;
+use hyper::status::StatusCode;
+use multirust;
+
+use {Error, Nightly, Result};
+
+/// How many nightlies to probe for at once.
+const PROBE_JOBS: usize = 8;
+
+/// How many days back to look for the latest nightly, in case recent builds failed.
+const LATEST_NIGHTLY_SEARCH_DAYS: i64 = 14;
+
+/// How old a date must be before a missing nightly for it is cached. A nightly may not have been
+/// published yet for the last day or so.
+const MISSING_CACHE_DELAY_DAYS: i64 = 2;
+
+/// Lists the nightlies available from `from` up to, but not including, `to`.
+pub fn list_available(mr_cfg: &multirust::Cfg,
+                      from: NaiveDate,
+                      to: NaiveDate)
+                      -> Result<Vec<Nightly>> {
+    assert!(from < to, "`from` must be less than `to`");
+    let mut dates = Vec::with_capacity((to - from).num_days() as usize);
+    let mut date = from;
+    while date < to {
+        dates.push(date);
+        date = date.succ();
+    }
+
+    let available = try!(availability(mr_cfg, &dates));
+    Ok(dates.into_iter()
+            .zip(available)
+            .filter(|&(_, available)| available)
+            .map(|(date, _)| Nightly::from(date))
+            .collect())
+}
+
+/// Finds the most recently published nightly.
+pub fn latest(mr_cfg: &multirust::Cfg) -> Result<Nightly> {
+    let today = chrono::UTC::today().naive_utc();
+    let dates: Vec<_> = (0..LATEST_NIGHTLY_SEARCH_DAYS)
+                            .map(|days| today - Duration::days(days))
+                            .collect();
+    let available = try!(availability(mr_cfg, &dates));
+    match dates.into_iter().zip(available).find(|&(_, available)| available) {
+        Some((date, _)) => Ok(Nightly::from(date)),
+        None => {
+            Err(Error::from(format!("no nightlies found in the last {} days",
+                                    LATEST_NIGHTLY_SEARCH_DAYS)))
+        }
+    }
+}
+
+/// Finds out whether there is a nightly for each of `dates`, using the cache where possible.
+fn availability(mr_cfg: &multirust::Cfg, dates: &[NaiveDate]) -> Result<Vec<bool>> {
+    let dist_root = &*mr_cfg.dist_root_url;
+    let mut cache = Cache::load(cache_path(mr_cfg), dist_root);
+    let unknown: Vec<_> = dates.iter()
+                               .cloned()
+                               .filter(|date| !cache.known.contains_key(date))
+                               .collect();
+
+    if !unknown.is_empty() {
+        let today = chrono::UTC::today().naive_utc();
+        for (date, available) in try!(probe(dist_root, unknown)) {
+            if available || today - date >= Duration::days(MISSING_CACHE_DELAY_DAYS) {
+                cache.known.insert(date, available);
+            } else {
+                cache.unsaved.insert(date, available);
+            }
+        }
+        try!(cache.save());
+    }
+
+    Ok(dates.iter().map(|date| cache.is_available(date)).collect())
+}
+
+/// Probes the dist server for the nightlies for `dates`, up to `PROBE_JOBS` at a time.
+fn probe(dist_root: &str, dates: Vec<NaiveDate>) -> Result<Vec<(NaiveDate, bool)>> {
+    let jobs = cmp::min(PROBE_JOBS, dates.len());
+    let dates = Arc::new(dates);
+    let next = Arc::new(AtomicUsize::new(0));
+    let (tx, rx) = mpsc::channel();
+    for _ in 0..jobs {
+        let (dates, next, tx) = (dates.clone(), next.clone(), tx.clone());
+        let dist_root = String::from(dist_root);
+        thread::spawn(move || {
+            let client = Client::new();
+            loop {
+                let idx = next.fetch_add(1, Ordering::SeqCst);
+                if idx >= dates.len() {
+                    break;
+                }
+                // Errors aren't `Send`, so only their messages are passed back.
+                let res = exists(&client, &dist_root, Nightly::from(dates[idx]))
+                              .map_err(|e| e.to_string());
+                if tx.send((dates[idx], res)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    drop(tx);
+
+    let mut found = Vec::with_capacity(dates.len());
+    for (date, res) in rx {
+        found.push((date, try!(res)));
+    }
+    Ok(found)
+}
+
+fn exists(client: &Client, dist_root: &str, nightly: Nightly) -> Result<bool> {
+    let manifest_url = nightly.to_toolchain_desc(None).manifest_url(dist_root);
+    let resp = try!(client.head(&manifest_url).send());
+    match resp.status {
+        StatusCode::NotFound => Ok(false),
+        status if status.is_success() => Ok(true),
+        status => Err(Error::from(format!("unexpected response for {}: {}", manifest_url, status))),
+    }
+}
+
+fn cache_path(mr_cfg: &multirust::Cfg) -> PathBuf {
+    mr_cfg.multirust_dir.join("bisect").join("nightlies")
+}
+
+/// Which nightlies are known to exist, saved as one `YYYY-MM-DD present|missing` line per date.
+///
+/// The cache is only a shortcut, so a cache that can't be read is treated as empty.
+struct Cache {
+    path: PathBuf,
+    dist_root: String,
+    known: BTreeMap<NaiveDate, bool>,
+    /// Results that are too recent to be trusted on later runs.
+    unsaved: BTreeMap<NaiveDate, bool>,
+}
+
+impl Cache {
+    fn load(path: PathBuf, dist_root: &str) -> Cache {
+        let known = Cache::read(&path, dist_root).unwrap_or(BTreeMap::new());
+        Cache {
+            path: path,
+            dist_root: String::from(dist_root),
+            known: known,
+            unsaved: BTreeMap::new(),
+        }
+    }
+
+    fn read(path: &Path, dist_root: &str) -> Result<BTreeMap<NaiveDate, bool>> {
+        let mut known = BTreeMap::new();
+        let mut lines = BufReader::new(try!(File::open(path))).lines();
+        // Results for another dist server are no use.
+        let header = try!(try!(lines.next().ok_or("empty cache")));
+        if header != format!("dist-root {}", dist_root) {
+            return Ok(known);
+        }
+        for line in lines {
+            let line = try!(line);
+            let mut parts = line.split(' ');
+            let date = try!(try!(parts.next().ok_or("malformed cache entry")).parse());
+            let available = match parts.next() {
+                Some("present") => true,
+                Some("missing") => false,
+                _ => return Err(Error::from(format!("malformed cache entry: {}", line))),
+            };
+            known.insert(date, available);
+        }
+        Ok(known)
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            try!(fs::create_dir_all(dir));
+        }
+        let mut file = try!(File::create(&self.path));
+        try!(writeln!(file, "dist-root {}", self.dist_root));
+        for (date, &available) in &self.known {
+            try!(writeln!(file,
+                          "{} {}",
+                          date,
+                          if available {
+                              "present"
+                          } else {
+                              "missing"
+                          }));
+        }
+        Ok(())
+    }
+
+    fn is_available(&self, date: &NaiveDate) -> bool {
+        self.known.get(date).or(self.unsaved.get(date)).cloned().unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    const DIST_ROOT: &'static str = "https://static.rust-lang.org/dist";
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_save_read() {
+        let path = env::temp_dir().join("rust-bisect-test-nightlies");
+        let mut cache = Cache::load(path.clone(), DIST_ROOT);
+        cache.known.insert(date("2015-11-13"), true);
+        cache.known.insert(date("2015-11-14"), false);
+        cache.unsaved.insert(date("2015-11-15"), true);
+        cache.save().unwrap();
+
+        let known = Cache::read(&path, DIST_ROOT).unwrap();
+        let other = Cache::read(&path, "https://example.com/dist").unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(cache.known, known);
+        assert_eq!(BTreeMap::new(), other);
+    }
+
+    #[test]
+    fn test_read_malformed() {
+        let path = env::temp_dir().join("rust-bisect-test-nightlies-malformed");
+        {
+            let mut file = File::create(&path).unwrap();
+            writeln!(file, "dist-root {}", DIST_ROOT).unwrap();
+            writeln!(file, "2015-11-13 present").unwrap();
+            writeln!(file, "2015-11-14 maybe").unwrap();
+        }
+        let res = Cache::read(&path, DIST_ROOT);
+        let cache = Cache::load(path.clone(), DIST_ROOT);
+        fs::remove_file(&path).unwrap();
+        assert!(res.is_err());
+        assert!(cache.known.is_empty());
+
+        assert!(Cache::read(&path, DIST_ROOT).is_err());
+    }
+}
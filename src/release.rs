@@ -0,0 +1,123 @@
+use std::{fmt, str};
+
+use chrono::{Duration, NaiveDate};
+use rust_install::dist;
+use semver;
+
+use {Error, Result};
+
+/// Length of the Rust release cycle. A nightly becomes beta, and six weeks later is released as
+/// stable.
+const RELEASE_CYCLE_DAYS: i64 = 42;
+
+/// A stable Rust release, such as `1.4.0`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Release {
+    pub version: semver::Version,
+}
+
+impl Release {
+    /// The approximate release date, assuming the six week release cycle has been kept since 1.0.
+    pub fn date(&self) -> NaiveDate {
+        let cycles = self.version.minor as i64;
+        NaiveDate::from_ymd(2015, 5, 15) + Duration::days(RELEASE_CYCLE_DAYS * cycles)
+    }
+
+    /// The approximate date of the nightly this release was branched from. A release spends one
+    /// cycle as beta, so this is around when the previous release came out.
+    pub fn branch_date(&self) -> NaiveDate {
+        self.date() - Duration::days(RELEASE_CYCLE_DAYS)
+    }
+
+    pub fn installer_url(&self, dist_root: &str, target_triple: &str) -> String {
+        format!("{}/rust-{}-{}{}",
+                dist_root,
+                self.version,
+                target_triple,
+                dist::get_installer_ext())
+    }
+}
+
+impl str::FromStr for Release {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Release> {
+        let version = try!(semver::Version::parse(s));
+        if version.major != 1 || !version.build.is_empty() {
+            return Err(Error::from("not a stable 1.x release"));
+        }
+        if !version.pre.is_empty() {
+            return Err(Error::from(format!("beta releases like {} can't be bisected across; use \
+                                            1.{}.0, the stable release it became, or the \
+                                            nightlies it was branched from",
+                                           s,
+                                           version.minor)));
+        }
+        Ok(Release { version: version })
+    }
+}
+
+impl fmt::Display for Release {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.version)
+    }
+}
+
+/// Lists the stable releases from `from` up to, but not including, `to`.
+///
+/// Only the first release of each minor version is listed after `from`; point releases rarely
+/// matter when bisecting.
+pub fn list_releases(from: &Release, to: &Release) -> Vec<Release> {
+    assert!(from < to, "`from` must be less than `to`");
+    let mut releases = vec![from.clone()];
+    for minor in (from.version.minor + 1)..(to.version.minor + 1) {
+        let release = Release {
+            version: semver::Version {
+                major: 1,
+                minor: minor,
+                patch: 0,
+                pre: Vec::new(),
+                build: Vec::new(),
+            },
+        };
+        if release < *to {
+            releases.push(release);
+        }
+    }
+    releases
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release(s: &str) -> Release {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(4, release("1.4.0").version.minor);
+        assert_eq!(1, release("1.5.1").version.patch);
+        let err = "1.6.0-beta".parse::<Release>().unwrap_err().to_string();
+        assert!(err.contains("beta releases"), "{}", err);
+        assert!("1.6.0-beta.2".parse::<Release>().is_err());
+        assert!("2.0.0".parse::<Release>().is_err());
+        assert!("nightly-2015-10-27".parse::<Release>().is_err());
+    }
+
+    #[test]
+    fn test_list_releases() {
+        assert_eq!(vec![release("1.4.0"), release("1.5.0"), release("1.6.0")],
+                   list_releases(&release("1.4.0"), &release("1.7.0")));
+        assert_eq!(vec![release("1.4.1"), release("1.5.0")],
+                   list_releases(&release("1.4.1"), &release("1.5.2")));
+        assert_eq!(vec![release("1.5.0")],
+                   list_releases(&release("1.5.0"), &release("1.5.1")));
+    }
+
+    #[test]
+    fn test_branch_date() {
+        assert_eq!(NaiveDate::from_ymd(2015, 5, 15), release("1.0.0").date());
+        assert_eq!(release("1.3.0").date(), release("1.4.0").branch_date());
+    }
+}
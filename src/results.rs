@@ -0,0 +1,169 @@
+//! Machine-readable results of a finished bisection, for wrappers and dashboards.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::str;
+use std::time::Duration;
+
+use rustc_serialize::json::{self, Json, ToJson};
+
+use session::{Session, Step};
+use {Error, Result, Version};
+
+/// How to report the result of a bisection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+impl Default for Format {
+    fn default() -> Format {
+        Format::Text
+    }
+}
+
+impl str::FromStr for Format {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Format> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(Error::from(format!("invalid format: {}", s))),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Results {
+    pub good: String,
+    pub bad: String,
+    /// The versions between `good` and `bad` that were bisected across.
+    pub candidates: Vec<String>,
+    pub steps: Vec<StepResult>,
+    /// Whether the bisection looked for a fix rather than a regression.
+    pub find_fix: bool,
+    /// The last good version, or the last bad one when looking for a fix.
+    pub before: VersionResult,
+    /// The first bad version, or the first good one when looking for a fix.
+    pub after: VersionResult,
+    /// The versions between `before` and `after` that could not be tested.
+    pub untestable: Vec<String>,
+    /// How likely the result is, for a probabilistic bisection.
+    pub confidence: Option<f64>,
+}
+
+#[derive(Debug)]
+pub struct StepResult {
+    pub version: String,
+    pub verdict: String,
+    pub exit_status: Option<i32>,
+    pub duration_secs: Option<f64>,
+}
+
+#[derive(Debug)]
+pub struct VersionResult {
+    pub version: String,
+    /// The rust-lang/rust commit the version's rustc was built from, if it could be found.
+    pub commit_hash: Option<String>,
+}
+
+impl Results {
+    /// Collects the results of the finished `session`, given the commit hashes of the versions
+    /// either side of the change.
+    pub fn new(session: &Session,
+               before_hash: Option<String>,
+               after_hash: Option<String>)
+               -> Results {
+        let (before, after) = session.boundary();
+        Results {
+            good: session.good.to_string(),
+            bad: session.bad.to_string(),
+            candidates: to_strings(&session.candidates),
+            steps: session.steps.iter().map(StepResult::new).collect(),
+            find_fix: session.find_fix,
+            before: VersionResult {
+                version: before.to_string(),
+                commit_hash: before_hash,
+            },
+            after: VersionResult {
+                version: after.to_string(),
+                commit_hash: after_hash,
+            },
+            untestable: to_strings(&session.candidates[session.bounds()]),
+            confidence: session.confidence(),
+        }
+    }
+
+    pub fn to_pretty_json(&self) -> String {
+        json::as_pretty_json(&self.to_json()).to_string()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut file = try!(File::create(path));
+        try!(writeln!(file, "{}", self.to_pretty_json()));
+        Ok(())
+    }
+}
+
+impl ToJson for Results {
+    fn to_json(&self) -> Json {
+        let mut obj = BTreeMap::new();
+        obj.insert(String::from("good"), self.good.to_json());
+        obj.insert(String::from("bad"), self.bad.to_json());
+        obj.insert(String::from("candidates"), self.candidates.to_json());
+        obj.insert(String::from("steps"), self.steps.to_json());
+        let (before, after) = if self.find_fix {
+            ("last_bad", "first_good")
+        } else {
+            ("last_good", "first_bad")
+        };
+        obj.insert(String::from("find_fix"), self.find_fix.to_json());
+        obj.insert(String::from(before), self.before.to_json());
+        obj.insert(String::from(after), self.after.to_json());
+        obj.insert(String::from("untestable"), self.untestable.to_json());
+        obj.insert(String::from("confidence"), self.confidence.to_json());
+        Json::Object(obj)
+    }
+}
+
+impl StepResult {
+    fn new(step: &Step) -> StepResult {
+        StepResult {
+            version: step.version.to_string(),
+            verdict: step.verdict.to_string(),
+            exit_status: step.exit_code,
+            duration_secs: step.duration.map(to_secs),
+        }
+    }
+}
+
+impl ToJson for StepResult {
+    fn to_json(&self) -> Json {
+        let mut obj = BTreeMap::new();
+        obj.insert(String::from("version"), self.version.to_json());
+        obj.insert(String::from("verdict"), self.verdict.to_json());
+        obj.insert(String::from("exit_status"), self.exit_status.to_json());
+        obj.insert(String::from("duration_secs"), self.duration_secs.to_json());
+        Json::Object(obj)
+    }
+}
+
+impl ToJson for VersionResult {
+    fn to_json(&self) -> Json {
+        let mut obj = BTreeMap::new();
+        obj.insert(String::from("version"), self.version.to_json());
+        obj.insert(String::from("commit_hash"), self.commit_hash.to_json());
+        Json::Object(obj)
+    }
+}
+
+fn to_strings(versions: &[Version]) -> Vec<String> {
+    versions.iter().map(|version| version.to_string()).collect()
+}
+
+fn to_secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9
+}
@@ -0,0 +1,584 @@
+//! Bisect sessions that are saved to disk after every step, so a bisection can be resumed or
+//! driven by hand.
+//!
+//! Sessions are saved as a plain text log, similar to git's `BISECT_LOG`: one `key value` entry
+//! per line, with `#` comments.
+
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use regex::Regex;
+
+use bisect::{Posterior, Satisfies, Search};
+use {CONFIDENCE, FLAKE_RATE, Cfg, Error, Regress, Result, Timeout, Verdict, Version, Vote};
+
+#[derive(Debug)]
+pub struct Session {
+    pub good: Version,
+    pub bad: Version,
+    pub cmd: Option<OsString>,
+    pub args: Vec<OsString>,
+    /// The single-file program compiled with each version instead of running `cmd`.
+    pub rust_file: Option<PathBuf>,
+    /// Whether the program compiled from `rust_file` is run too.
+    pub run_binary: bool,
+    /// What decides the verdicts, kept so that all of the session's verdicts mean the same thing.
+    pub regress: Regress,
+    pub bad_pattern: Option<Regex>,
+    pub good_pattern: Option<Regex>,
+    pub timeout: Option<Timeout>,
+    pub runs: usize,
+    pub vote: Vote,
+    /// Whether each version builds in a target dir of its own.
+    pub isolate_target_dir: bool,
+    /// Variables set in the command's environment.
+    pub env: Vec<(String, String)>,
+    /// The components to install for each nightly; empty for the whole `rust` package.
+    pub components: Vec<String>,
+    /// The extra targets to install the standard library for.
+    pub targets: Vec<String>,
+    /// The host to install toolchains for, if not the native one.
+    pub host: Option<String>,
+    /// Whether this is looking for a fix, in which case `bad` is the older endpoint.
+    pub find_fix: bool,
+    /// Whether verdicts are weighed against each other rather than taken as final, for flaky
+    /// commands.
+    pub probabilistic: bool,
+    /// The versions being bisected across. `newer()` follows the last of them.
+    pub candidates: Vec<Version>,
+    pub steps: Vec<Step>,
+    search: Strategy,
+}
+
+/// How the candidates are searched.
+#[derive(Clone, Debug)]
+enum Strategy {
+    Binary(Search),
+    Probabilistic(Posterior),
+}
+
+impl Strategy {
+    fn next(&self) -> Option<usize> {
+        match *self {
+            Strategy::Binary(ref search) => search.next(),
+            Strategy::Probabilistic(ref search) => search.next(),
+        }
+    }
+
+    fn next_batch(&self, k: usize) -> Vec<usize> {
+        match *self {
+            Strategy::Binary(ref search) => search.next_batch(k),
+            Strategy::Probabilistic(ref search) => search.next_batch(k),
+        }
+    }
+
+    fn record(&mut self, idx: usize, satisfies: Satisfies) {
+        match *self {
+            Strategy::Binary(ref mut search) => search.record(idx, satisfies),
+            Strategy::Probabilistic(ref mut search) => search.record(idx, satisfies),
+        }
+    }
+
+    fn bounds(&self) -> Range<usize> {
+        match *self {
+            Strategy::Binary(ref search) => search.bounds(),
+            Strategy::Probabilistic(ref search) => search.bounds(),
+        }
+    }
+}
+
+/// The verdict on one version, along with how the command went if it was run.
+#[derive(Clone, Debug)]
+pub struct Step {
+    pub version: Version,
+    pub verdict: Verdict,
+    /// The command's exit code, unless it was killed by a signal or wasn't run.
+    pub exit_code: Option<i32>,
+    /// How long the command ran for, if it was run.
+    pub duration: Option<Duration>,
+}
+
+impl Step {
+    /// A step with a verdict that didn't come from running the command, such as one given by hand.
+    pub fn without_command(version: Version, verdict: Verdict) -> Step {
+        Step {
+            version: version,
+            verdict: verdict,
+            exit_code: None,
+            duration: None,
+        }
+    }
+}
+
+impl Session {
+    /// Starts a session with the command and what decides its verdicts, the components, targets
+    /// and host, the direction and the search strategy from `cfg`.
+    pub fn new(cfg: &Cfg, good: Version, bad: Version, candidates: Vec<Version>) -> Session {
+        let search = if cfg.probabilistic {
+            Strategy::Probabilistic(Posterior::new(candidates.len(), FLAKE_RATE, CONFIDENCE))
+        } else {
+            Strategy::Binary(Search::new(candidates.len()))
+        };
+        Session {
+            good: good,
+            bad: bad,
+            cmd: cfg.cmd.clone(),
+            args: cfg.args.clone(),
+            rust_file: cfg.rust_file.clone(),
+            run_binary: cfg.run_binary,
+            regress: cfg.regress,
+            bad_pattern: cfg.bad_pattern.clone(),
+            good_pattern: cfg.good_pattern.clone(),
+            timeout: cfg.timeout,
+            runs: cfg.runs,
+            vote: cfg.vote,
+            isolate_target_dir: cfg.isolate_target_dir,
+            env: cfg.env.clone(),
+            components: cfg.components.clone(),
+            targets: cfg.targets.clone(),
+            host: cfg.host.clone(),
+            find_fix: cfg.find_fix,
+            probabilistic: cfg.probabilistic,
+            candidates: candidates,
+            steps: Vec::new(),
+            search: search,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Session> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(_) => return Err(Error::from("no bisect session in progress")),
+        };
+
+        let mut good = None;
+        let mut bad = None;
+        let mut cmd = None;
+        let mut args = Vec::new();
+        let mut rust_file = None;
+        let mut run_binary = false;
+        let mut regress = Regress::default();
+        let mut bad_pattern = None;
+        let mut good_pattern = None;
+        let mut timeout = None;
+        let mut runs = 1;
+        let mut vote = Vote::default();
+        let mut isolate_target_dir = false;
+        let mut env = Vec::new();
+        let mut components = Vec::new();
+        let mut targets = Vec::new();
+        let mut host = None;
+        let mut find_fix = false;
+        let mut probabilistic = false;
+        let mut candidates = Vec::new();
+        let mut steps = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = try!(line);
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, ' ');
+            let key = parts.next().unwrap();
+            let value = try!(parts.next()
+                                  .ok_or_else(|| format!("malformed session entry: {}", line)));
+            match key {
+                "good" => good = Some(try!(value.parse())),
+                "bad" => bad = Some(try!(value.parse())),
+                "command" => cmd = Some(OsString::from(value)),
+                "arg" => args.push(OsString::from(value)),
+                "rust-file" => rust_file = Some(PathBuf::from(value)),
+                "run-binary" => run_binary = try!(value.parse()),
+                "regress" => regress = try!(value.parse()),
+                "bad-pattern" => bad_pattern = Some(try!(Regex::new(value))),
+                "good-pattern" => good_pattern = Some(try!(Regex::new(value))),
+                "timeout" => timeout = Some(try!(parse_timeout(value))),
+                "runs" => runs = try!(value.parse()),
+                "vote" => vote = try!(value.parse()),
+                "isolate-target-dir" => isolate_target_dir = try!(value.parse()),
+                "env" => {
+                    let mut parts = value.splitn(2, '=');
+                    let name = parts.next().unwrap();
+                    let value = try!(parts.next()
+                                          .ok_or_else(|| format!("malformed env entry: {}", line)));
+                    env.push((String::from(name), String::from(value)));
+                }
+                "component" => components.push(String::from(value)),
+                "target" => targets.push(String::from(value)),
+                "host" => host = Some(String::from(value)),
+                "find-fix" => find_fix = try!(value.parse()),
+                "probabilistic" => probabilistic = try!(value.parse()),
+                "candidate" => candidates.push(try!(value.parse())),
+                "verdict" => steps.push(try!(parse_step(value))),
+                _ => return Err(Error::from(format!("unknown session entry: {}", line))),
+            }
+        }
+
+        let good = try!(good.ok_or("session is missing `good`"));
+        let bad = try!(bad.ok_or("session is missing `bad`"));
+        let cfg = Cfg {
+            cmd: cmd,
+            args: args,
+            rust_file: rust_file,
+            run_binary: run_binary,
+            regress: regress,
+            bad_pattern: bad_pattern,
+            good_pattern: good_pattern,
+            timeout: timeout,
+            runs: runs,
+            vote: vote,
+            isolate_target_dir: isolate_target_dir,
+            env: env,
+            components: components,
+            targets: targets,
+            host: host,
+            find_fix: find_fix,
+            probabilistic: probabilistic,
+            ..Cfg::default()
+        };
+        let mut session = Session::new(&cfg, good, bad, candidates);
+        for step in steps {
+            try!(session.record(step));
+        }
+        Ok(session)
+    }
+
+    /// Saves the session to `path`. It is written to `path.tmp` first and then moved into place,
+    /// so that being interrupted part way can't lose the verdicts saved before.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            try!(fs::create_dir_all(dir));
+        }
+        let tmp = path.with_extension("tmp");
+        let mut file = try!(File::create(&tmp));
+
+        try!(writeln!(file, "good {}", self.good));
+        try!(writeln!(file, "bad {}", self.bad));
+        if let Some(ref cmd) = self.cmd {
+            try!(writeln!(file, "command {}", try!(to_str(cmd))));
+        }
+        for arg in &self.args {
+            try!(writeln!(file, "arg {}", try!(to_str(arg))));
+        }
+        if let Some(ref rust_file) = self.rust_file {
+            try!(writeln!(file, "rust-file {}", try!(to_str(rust_file.as_os_str()))));
+            try!(writeln!(file, "run-binary {}", self.run_binary));
+        }
+        try!(writeln!(file, "regress {}", self.regress));
+        if let Some(ref pattern) = self.bad_pattern {
+            try!(writeln!(file, "bad-pattern {}", pattern));
+        }
+        if let Some(ref pattern) = self.good_pattern {
+            try!(writeln!(file, "good-pattern {}", pattern));
+        }
+        if let Some(timeout) = self.timeout {
+            try!(writeln!(file, "timeout {} {}", timeout.secs, timeout.verdict));
+        }
+        if self.runs > 1 {
+            try!(writeln!(file, "runs {}", self.runs));
+            try!(writeln!(file, "vote {}", self.vote));
+        }
+        if self.isolate_target_dir {
+            try!(writeln!(file, "isolate-target-dir true"));
+        }
+        for &(ref name, ref value) in &self.env {
+            try!(writeln!(file, "env {}={}", name, value));
+        }
+        for component in &self.components {
+            try!(writeln!(file, "component {}", component));
+        }
+        for target in &self.targets {
+            try!(writeln!(file, "target {}", target));
+        }
+        if let Some(ref host) = self.host {
+            try!(writeln!(file, "host {}", host));
+        }
+        if self.find_fix {
+            try!(writeln!(file, "find-fix true"));
+        }
+        if self.probabilistic {
+            try!(writeln!(file, "probabilistic true"));
+        }
+        for candidate in &self.candidates {
+            try!(writeln!(file, "candidate {}", candidate));
+        }
+        for step in &self.steps {
+            try!(write!(file, "verdict {} {}", step.version, step.verdict));
+            if let Some(code) = step.exit_code {
+                try!(write!(file, " exit {}", code));
+            }
+            if let Some(duration) = step.duration {
+                try!(write!(file, " millis {}", to_millis(duration)));
+            }
+            try!(writeln!(file, ""));
+        }
+
+        let bounds = self.bounds();
+        try!(writeln!(file,
+                      "# bounds: {} to {}",
+                      self.candidates.get(bounds.start).unwrap_or(self.newer()),
+                      self.candidates.get(bounds.end).unwrap_or(self.newer())));
+        try!(file.sync_all());
+        drop(file);
+        try!(fs::rename(&tmp, path));
+        Ok(())
+    }
+
+    /// Returns the version to test next, or `None` if the bisection is over.
+    pub fn next(&self) -> Option<&Version> {
+        self.search.next().map(|idx| &self.candidates[idx])
+    }
+
+    /// Returns up to `k` versions to test at once, splitting the remaining candidates into `k + 1`
+    /// segments.
+    pub fn next_batch(&self, k: usize) -> Vec<Version> {
+        self.search.next_batch(k).into_iter().map(|idx| self.candidates[idx].clone()).collect()
+    }
+
+    /// The endpoint before the candidates: `good`, or `bad` when looking for a fix.
+    pub fn older(&self) -> &Version {
+        if self.find_fix {
+            &self.bad
+        } else {
+            &self.good
+        }
+    }
+
+    /// The endpoint after the candidates: `bad`, or `good` when looking for a fix.
+    pub fn newer(&self) -> &Version {
+        if self.find_fix {
+            &self.good
+        } else {
+            &self.bad
+        }
+    }
+
+    /// Returns the versions that could be tested after `version`, depending on whether it turns
+    /// out good or bad.
+    pub fn upcoming(&self, version: &Version) -> Vec<Version> {
+        let idx = match self.candidates.iter().position(|c| c == version) {
+            Some(idx) => idx,
+            None => return Vec::new(),
+        };
+        let mut upcoming = Vec::new();
+        for &verdict in &[Verdict::Good, Verdict::Bad] {
+            let mut search = self.search.clone();
+            search.record(idx, self.satisfies(verdict));
+            if let Some(next) = search.next() {
+                if !upcoming.contains(&self.candidates[next]) {
+                    upcoming.push(self.candidates[next].clone());
+                }
+            }
+        }
+        upcoming
+    }
+
+    /// Records a step for one of the candidates that may still be the first changed version. In a
+    /// probabilistic session, any candidate can be tested again.
+    pub fn record(&mut self, step: Step) -> Result<()> {
+        let idx = try!(self.candidates.iter().position(|c| *c == step.version).ok_or_else(|| {
+            format!("{} is not being bisected across", step.version)
+        }));
+        let bounds = self.bounds();
+        if !self.probabilistic && (idx < bounds.start || idx >= bounds.end) {
+            return Err(Error::from(format!("{} is already known to be {}",
+                                           step.version,
+                                           if (idx < bounds.start) != self.find_fix {
+                                               "good"
+                                           } else {
+                                               "bad"
+                                           })));
+        }
+
+        self.search.record(idx, self.satisfies(step.verdict));
+        self.steps.push(step);
+        Ok(())
+    }
+
+    /// Returns the last version known to be like the older endpoint and the first known to be
+    /// like the newer one: the last good and first bad, or the last bad and first good when
+    /// looking for a fix. Once the bisection is over, the first changed version is either the
+    /// latter or one of the untestable versions in between.
+    pub fn boundary(&self) -> (&Version, &Version) {
+        let bounds = self.bounds();
+        let before = match bounds.start {
+            0 => self.older(),
+            start => &self.candidates[start - 1],
+        };
+        (before, self.candidates.get(bounds.end).unwrap_or(self.newer()))
+    }
+
+    /// Returns the range `lo..hi` of candidates such that the first changed version is either at
+    /// `hi` or in `lo..hi`; `hi` is `candidates.len()` for `newer()` itself.
+    pub fn bounds(&self) -> Range<usize> {
+        self.search.bounds()
+    }
+
+    /// The number of candidates that may still be the first changed version: those in `bounds()`,
+    /// or all of them in a probabilistic session, where none are ruled out for good.
+    pub fn remaining(&self) -> usize {
+        if self.probabilistic {
+            self.candidates.len()
+        } else {
+            self.bounds().len()
+        }
+    }
+
+    /// For a probabilistic session, the probability that the first changed version is in
+    /// `bounds()`.
+    pub fn confidence(&self) -> Option<f64> {
+        match self.search {
+            Strategy::Binary(_) => None,
+            Strategy::Probabilistic(ref search) => Some(search.confidence()),
+        }
+    }
+
+    /// Maps a verdict onto the search for the first version like the newer endpoint.
+    fn satisfies(&self, verdict: Verdict) -> Satisfies {
+        match (verdict, self.find_fix) {
+            (Verdict::Skip, _) => Satisfies::Unknown,
+            (Verdict::Bad, false) | (Verdict::Good, true) => Satisfies::Yes,
+            (Verdict::Good, false) | (Verdict::Bad, true) => Satisfies::No,
+        }
+    }
+}
+
+/// Parses a `VERSION VERDICT [exit CODE] [millis DURATION]` verdict entry.
+fn parse_step(value: &str) -> Result<Step> {
+    let malformed = || format!("malformed verdict: {}", value);
+    let mut parts = value.split(' ');
+    let version = try!(try!(parts.next().ok_or_else(&malformed)).parse());
+    let verdict = try!(try!(parts.next().ok_or_else(&malformed)).parse());
+    let mut step = Step::without_command(version, verdict);
+    while let Some(key) = parts.next() {
+        let value = try!(parts.next().ok_or_else(&malformed));
+        match key {
+            "exit" => step.exit_code = Some(try!(value.parse())),
+            "millis" => step.duration = Some(Duration::from_millis(try!(value.parse()))),
+            _ => return Err(Error::from(malformed())),
+        }
+    }
+    Ok(step)
+}
+
+/// Parses a `SECS VERDICT` timeout entry.
+fn parse_timeout(value: &str) -> Result<Timeout> {
+    let mut parts = value.splitn(2, ' ');
+    let secs = try!(parts.next().unwrap().parse());
+    let verdict = try!(try!(parts.next().ok_or_else(|| format!("malformed timeout: {}", value)))
+                           .parse());
+    Ok(Timeout {
+        secs: secs,
+        verdict: verdict,
+    })
+}
+
+fn to_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + duration.subsec_nanos() as u64 / 1000000
+}
+
+fn to_str(s: &OsStr) -> Result<&str> {
+    s.to_str().ok_or_else(|| Error::from(format!("can't save non-UTF-8 value: {:?}", s)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::ffi::OsString;
+    use std::fs;
+    use std::time::Duration;
+
+    use regex::Regex;
+
+    use super::*;
+    use {Cfg, Regress, Timeout, Verdict, Version, Vote};
+
+    fn version(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_step() {
+        let step = parse_step("nightly-2015-11-13 bad exit 101 millis 1500").unwrap();
+        assert_eq!(version("nightly-2015-11-13"), step.version);
+        assert_eq!(Verdict::Bad, step.verdict);
+        assert_eq!(Some(101), step.exit_code);
+        assert_eq!(Some(Duration::from_millis(1500)), step.duration);
+
+        let step = parse_step("1.5.0 skip").unwrap();
+        assert_eq!(version("1.5.0"), step.version);
+        assert_eq!(Verdict::Skip, step.verdict);
+        assert_eq!(None, step.exit_code);
+        assert_eq!(None, step.duration);
+
+        assert!(parse_step("nightly-2015-11-13").is_err());
+        assert!(parse_step("nightly-2015-11-13 good exit").is_err());
+        assert!(parse_step("nightly-2015-11-13 good speed 3").is_err());
+    }
+
+    #[test]
+    fn test_save_load() {
+        let cfg = Cfg {
+            cmd: Some(OsString::from("cargo")),
+            args: vec![OsString::from("build"), OsString::from("--release")],
+            components: vec![String::from("rustc"), String::from("rust-src")],
+            targets: vec![String::from("i686-unknown-linux-musl")],
+            host: Some(String::from("i686-unknown-linux-gnu")),
+            regress: Regress::Ice,
+            bad_pattern: Some(Regex::new("error: .* overflow").unwrap()),
+            timeout: Some(Timeout {
+                secs: 300,
+                verdict: Verdict::Skip,
+            }),
+            runs: 3,
+            vote: Vote::Majority,
+            env: vec![(String::from("RUST_BACKTRACE"), String::from("1"))],
+            ..Cfg::default()
+        };
+        let candidates: Vec<_> = ["nightly-2015-10-27", "nightly-2015-10-28", "nightly-2015-10-29"]
+                                     .iter()
+                                     .map(|s| version(s))
+                                     .collect();
+        let mut session = Session::new(&cfg,
+                                       version("nightly-2015-10-27"),
+                                       version("nightly-2015-10-30"),
+                                       candidates.clone());
+        let mut step = Step::without_command(version("nightly-2015-10-28"), Verdict::Good);
+        step.exit_code = Some(0);
+        step.duration = Some(Duration::from_millis(2500));
+        session.record(step).unwrap();
+
+        let path = env::temp_dir().join("rust-bisect-test-session");
+        // Saving again replaces the first save, without leaving the temporary file behind.
+        session.save(&path).unwrap();
+        session.save(&path).unwrap();
+        assert!(!path.with_extension("tmp").exists());
+        let loaded = Session::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(session.good, loaded.good);
+        assert_eq!(session.bad, loaded.bad);
+        assert_eq!(session.cmd, loaded.cmd);
+        assert_eq!(session.args, loaded.args);
+        assert_eq!(session.components, loaded.components);
+        assert_eq!(session.targets, loaded.targets);
+        assert_eq!(session.host, loaded.host);
+        assert_eq!(Regress::Ice, loaded.regress);
+        assert_eq!(Some(String::from("error: .* overflow")),
+                   loaded.bad_pattern.as_ref().map(|pattern| pattern.to_string()));
+        assert!(loaded.good_pattern.is_none());
+        let timeout = loaded.timeout.unwrap();
+        assert_eq!((300, Verdict::Skip), (timeout.secs, timeout.verdict));
+        assert_eq!((3, Vote::Majority), (loaded.runs, loaded.vote));
+        assert_eq!(session.env, loaded.env);
+        assert_eq!(candidates, loaded.candidates);
+        assert_eq!(1, loaded.steps.len());
+        assert_eq!(Some(0), loaded.steps[0].exit_code);
+        assert_eq!(Some(Duration::from_millis(2500)), loaded.steps[0].duration);
+        assert_eq!(session.bounds(), loaded.bounds());
+        assert_eq!(session.next(), loaded.next());
+    }
+}